@@ -1,240 +1,817 @@
-extern crate indicatif;
 extern crate regex;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Write};
-use std::net::{IpAddr, Ipv4Addr, Shutdown, TcpStream};
-use std::path::Path;
-use std::str::from_utf8;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use lazy_static::lazy_static;
 use regex::Regex;
 
+use crate::anime_output::{download_path, part_path, PartialDownload};
+use crate::anime_verify::{self, MismatchAction, Verification};
+use crate::auth::{Authentication, Credentials};
+use crate::config::{NickStrategy, Timeouts};
+use crate::connection::{connect_tcp, IrcStream, ServerAddress};
+use crate::error::Error;
+use crate::events::{Event, EventListener};
+use crate::irc::{DccAddress, Message};
+use crate::xdcc::{strip_formatting, BotReply};
+
 lazy_static! {
+    // Bodies of the CTCP messages sent by bots
     static ref DCC_SEND_REGEX: Regex =
-        Regex::new(r#"DCC SEND (?:"([^"]+)"|(\S+)) (\d+) (\d+) (\d+)"#).unwrap();
+        Regex::new(r#"^DCC SEND (?:"([^"]+)"|(\S+)) (\S+) (\d+) (\d+)(?: (\S+))?"#).unwrap();
     static ref DCC_ACCEPT_REGEX: Regex =
-        Regex::new(r#"DCC ACCEPT (?:"([^"]+)"|(\S+)) (\d+) (\d+)"#).unwrap();
-    static ref PING_REGEX: Regex = Regex::new(r#"PING :\d+"#).unwrap();
-    static ref JOIN_REGEX: Regex = Regex::new(r#"JOIN :#.*"#).unwrap();
-    // IRC numeric replies indicating server is ready for commands
-    static ref MOTD_END_REGEX: Regex = Regex::new(r#":\S+ (376|422) "#).unwrap(); // RPL_ENDOFMOTD or ERR_NOMOTD
+        Regex::new(r#"^DCC ACCEPT (?:"([^"]+)"|(\S+)) (\d+) (\d+)(?: (\S+))?"#).unwrap();
 }
 
+/// Bots do not announce the end of their pack list, so stop listening once it stops flowing
+const PACK_LIST_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Pack lists sent over DCC are kept in memory, larger offers are refused
+const MAX_PACK_LIST_SIZE: usize = 4 * 1024 * 1024;
+
+/// What to download and how. Build it with [`IRCRequest::new`], then change the
+/// settings which differ from the defaults: fields are added over time.
+#[non_exhaustive]
 pub struct IRCRequest {
+    /// `host[:port]`, `irc://host[:port]` or `ircs://host[:port]`
     pub server: String,
     pub channel: String,
     pub nickname: String,
+    /// How to pick another nickname when `nickname` is taken
+    pub nick_strategy: NickStrategy,
+    /// Alternative nicknames to try before giving up
+    pub nick_attempts: u32,
     pub bot: String,
     pub packages: Vec<i32>,
+    /// Filenames of the packages, when known. Offers for other files are rejected.
+    pub filenames: Vec<String>,
+    /// Connect over TLS, implied by an `ircs://` server
+    pub tls: bool,
+    /// PEM certificate to trust on top of the system ones, see [`IrcStream::connect`]
+    pub tls_ca: Option<PathBuf>,
+    /// Trust only `tls_ca`, not the system certificates
+    pub tls_pin: bool,
+    /// Account to identify with before joining the channel
+    pub credentials: Option<Credentials>,
+    /// Directory the files are saved in, the current one when empty
+    pub output_dir: PathBuf,
+    /// Subfolder of `output_dir` for each file, see [`download_path`]
+    pub subfolder: Option<String>,
+    /// What to do with files which do not match the CRC32 of their filename
+    pub on_mismatch: MismatchAction,
+    /// Local ports to listen on for passive DCC offers, any free port when `None`
+    pub dcc_ports: Option<RangeInclusive<u16>>,
+    /// Address given to bots making passive DCC offers, the local address of the IRC
+    /// connection when `None`, which is wrong behind NAT
+    pub external_ip: Option<IpAddr>,
+    /// How long each step may take, see [`Timeouts`]
+    pub timeouts: Timeouts,
+}
+
+impl IRCRequest {
+    /// A request for `packages` of `bot`, over a plain connection without any
+    /// account, saving files in the current directory.
+    pub fn new(server: &str, channel: &str, nickname: &str, bot: &str, packages: Vec<i32>) -> IRCRequest {
+        IRCRequest {
+            server: server.to_string(),
+            channel: channel.to_string(),
+            nickname: nickname.to_string(),
+            nick_strategy: NickStrategy::Underscore,
+            nick_attempts: 3,
+            bot: bot.to_string(),
+            packages,
+            filenames: vec![],
+            tls: false,
+            tls_ca: None,
+            tls_pin: false,
+            credentials: None,
+            output_dir: PathBuf::new(),
+            subfolder: None,
+            on_mismatch: MismatchAction::Keep,
+            dcc_ports: None,
+            external_ip: None,
+            timeouts: Timeouts::default(),
+        }
+    }
+}
+
+/// Tracks the nickname we register with, switching to alternatives when it is refused.
+struct Nickname {
+    current: String,
+    attempts: u32,
+}
+
+impl Nickname {
+    fn new(request: &IRCRequest) -> Nickname {
+        Nickname {
+            current: request.nickname.clone(),
+            attempts: 0,
+        }
+    }
+
+    /// Answers a refused nickname with the next alternative, or fails once they are
+    /// exhausted. Invalid nicknames fail right away, alternatives would be invalid too.
+    fn handle_refusal(
+        &mut self,
+        message: &Message,
+        request: &IRCRequest,
+        stream: &mut IrcStream,
+        listener: &dyn EventListener,
+    ) -> Result<(), Error> {
+        // ERR_ERRONEUSNICKNAME, ERR_NICKNAMEINUSE or ERR_NICKCOLLISION
+        if !["432", "433", "436"].contains(&message.command.as_str()) {
+            return Ok(());
+        }
+        if message.command == "432" {
+            return Err(Error::InvalidInput(format!(
+                "The server refused the nickname '{}' as invalid. Please choose another one.",
+                self.current
+            )));
+        }
+
+        self.attempts += 1;
+        let alternative = match request.nick_strategy.alternative(&request.nickname, self.attempts) {
+            Some(nickname) if self.attempts <= request.nick_attempts => nickname,
+            _ => {
+                let alternatives = if self.attempts > 1 { " and so are its alternatives" } else { "" };
+                return Err(Error::Irc(
+                    format!(
+                        "The nickname '{}' is already in use{}. Please choose another one.",
+                        request.nickname, alternatives
+                    ),
+                    None,
+                ));
+            }
+        };
+        listener.on_event(&Event::NicknameRefused {
+            nickname: self.current.clone(),
+            alternative: alternative.clone(),
+        });
+        stream
+            .write_all(format!("NICK {}\r\n", alternative).as_bytes())
+            .map_err(Error::network("Failed to change nickname"))?;
+        self.current = alternative;
+        Ok(())
+    }
 }
 
 struct DCCSend {
+    /// As offered by the bot, which expects it back when resuming
     filename: String,
-    ip: IpAddr,
+    /// Where the file is saved, see [`download_path`]. It is written to its
+    /// [`part_path`] until complete.
+    path: PathBuf,
+    bot: String,
+    /// The requested pack the offer is for, when known
+    pack: Option<i32>,
+    on_mismatch: MismatchAction,
+    address: DccAddress,
     port: String,
     file_size: usize,
     resume_position: usize,
+    /// Identifies passive offers, which have no port: the bot connects to us instead
+    token: Option<String>,
+    /// Where we wait for the bot to connect, once a passive offer is answered
+    passive_listener: Option<TcpListener>,
+    timeouts: Timeouts,
 }
 
-pub fn connect_and_download(request: IRCRequest, shutdown: Arc<AtomicBool>, on_start: fn(String) -> ()) -> Result<(), String> {
-    let mut download_handles = Vec::new();
+impl DCCSend {
+    fn is_passive(&self) -> bool {
+        self.port == "0"
+    }
+
+    /// Identifies the offer in DCC ACCEPT messages: its port, or its token when passive.
+    fn resume_key(&self) -> String {
+        match &self.token {
+            Some(token) if self.is_passive() => token.clone(),
+            _ => self.port.clone(),
+        }
+    }
+}
+
+pub fn connect_and_download(
+    request: IRCRequest,
+    shutdown: Arc<AtomicBool>,
+    listener: Arc<dyn EventListener>,
+) -> Result<(), Error> {
+    let mut download_handles: Vec<thread::JoinHandle<Result<(), Error>>> = Vec::new();
     let mut has_joined = false;
     let mut server_ready = false; // Wait for MOTD end before joining
-    let mp = MultiProgress::new();
     let mut pending_resumes: HashMap<String, DCCSend> = HashMap::new();
     let mut packages_requested = 0;
+    let mut offered_files = HashSet::new();
+    let mut queued = false;
 
-    // Show connection status
-    let spinner = mp.add(ProgressBar::new_spinner());
-    spinner.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.cyan} {msg}")
-            .unwrap()
-    );
-    spinner.enable_steady_tick(Duration::from_millis(100));
-    spinner.set_message(format!("Connecting to {}...", request.server));
-
-    let mut stream = log_in(&request).map_err(|e| {
-        spinner.finish_and_clear();
-        format!("Failed to connect: {}", e)
-    })?;
-
-    spinner.set_message(format!("Connected! Joining #{}...", request.channel));
+    listener.on_event(&Event::Connecting {
+        server: request.server.clone(),
+    });
+    let mut authentication = Authentication::new(request.credentials.clone());
+    let mut stream = log_in(&request, &authentication)?;
+    listener.on_event(&Event::Connected {
+        server: request.server.clone(),
+    });
+    let mut nickname = Nickname::new(&request);
 
-    let mut message_buffer = String::new();
-    let mut last_activity = std::time::Instant::now();
-    let connection_timeout = Duration::from_secs(60);
+    let mut message_buffer = Vec::new();
+    let mut watchdog = Watchdog::new(request.timeouts);
 
     while download_handles.len() < request.packages.len() {
         // Check for shutdown signal
         if shutdown.load(Ordering::SeqCst) {
-            spinner.finish_and_clear();
-            eprintln!("\nInterrupted, cancelling downloads...");
-
             // Cancel any pending/in-progress XDCC transfers
             if packages_requested > download_handles.len() {
                 stream.write_all(format!("PRIVMSG {} :xdcc cancel\r\n", request.bot).as_bytes()).ok();
             }
+            // and leave the queue, so that the bot does not send the packs later
+            if queued {
+                stream.write_all(format!("PRIVMSG {} :xdcc remove\r\n", request.bot).as_bytes()).ok();
+            }
 
             stream.write_all("QUIT :Interrupted by user\r\n".as_bytes()).ok();
-            stream.shutdown(Shutdown::Both).ok();
-            return Err("Interrupted by user".to_string());
+            stream.shutdown();
+            return Err(Error::Interrupted);
         }
 
-        // Check for overall connection timeout
-        if last_activity.elapsed() > connection_timeout {
-            spinner.finish_and_clear();
+        // Bots may keep us in their queue, or busy with another pack, for long
+        let transferring = download_handles.iter().any(|handle| !handle.is_finished());
+        watchdog.enter(if !server_ready || !authentication.is_done() {
+            Stage::Registration
+        } else if packages_requested == 0 {
+            Stage::Join
+        } else if queued || transferring {
+            Stage::Waiting
+        } else {
+            Stage::Offer
+        });
+        if let Err(e) = watchdog.check(&mut stream, &request) {
             stream.write_all("QUIT :Connection timeout\r\n".as_bytes()).ok();
-            stream.shutdown(Shutdown::Both).ok();
-            return Err("Connection timed out waiting for server response. Please try again.".to_string());
+            stream.shutdown();
+            return Err(e);
         }
 
-        let message = match read_next_message(&mut stream, &mut message_buffer) {
-            Ok(msg) => {
-                last_activity = std::time::Instant::now();
-                msg
+        let line = match read_next_message(&mut stream, &mut message_buffer) {
+            Ok(line) => {
+                watchdog.received();
+                line
             }
             Err(e) => {
                 if e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut {
                     // Timeout after 1 second, continue to check shutdown flag
                     continue;
                 }
-                return Err(format!("Connection error: {}", e));
+                return Err(Error::network("Lost connection to the IRC server")(e));
             }
         };
+        let message = match Message::parse(&line) {
+            Some(message) => message,
+            None => continue,
+        };
+
+        // The server may refuse our nickname until we are registered
+        if !server_ready {
+            nickname.handle_refusal(&message, &request, &mut stream, listener.as_ref())?;
+        }
 
         // Check if server has completed welcome sequence
-        if !server_ready && MOTD_END_REGEX.is_match(&message) {
+        if !server_ready && is_motd_end(&message) {
             server_ready = true;
-            spinner.set_message("Server ready, joining channel...");
         }
+        authentication.handle(&message, server_ready, &mut stream, listener.as_ref())?;
 
         // Always respond to PINGs
-        if PING_REGEX.is_match(&message) {
-            let pong = message.replace("PING", "PONG");
-            stream.write_all(pong.as_bytes()).map_err(|e| format!("Failed to send PONG: {}", e))?;
-        }
+        answer_ping(&message, &mut stream)?;
 
-        // Join channel only after server is ready and we haven't joined yet
-        if server_ready && !has_joined {
+        // Join channel only after server is ready and we are identified, some channels require it
+        if server_ready && authentication.is_done() && !has_joined {
             let channel_join_cmd = format!("JOIN #{}\r\n", request.channel);
-            stream.write_all(channel_join_cmd.as_bytes()).map_err(|e| format!("Failed to join channel: {}", e))?;
+            stream.write_all(channel_join_cmd.as_bytes()).map_err(Error::network("Failed to join channel"))?;
             has_joined = true;
-            spinner.set_message(format!("Joining #{}...", request.channel));
         }
-        if JOIN_REGEX.is_match(&message) {
-            spinner.set_message(format!("Requesting {} package(s) from {}...", request.packages.len(), request.bot));
+        if is_own_join(&message, &nickname.current, &request.channel) {
+            listener.on_event(&Event::Joined {
+                channel: request.channel.clone(),
+            });
             for package in &request.packages {
                 let xdcc_send_cmd = format!("PRIVMSG {} :xdcc send #{}\r\n", request.bot, package);
-                stream.write_all(xdcc_send_cmd.as_bytes()).map_err(|e| format!("Failed to request package: {}", e))?;
+                stream.write_all(xdcc_send_cmd.as_bytes()).map_err(Error::network("Failed to request package"))?;
                 packages_requested += 1;
+                listener.on_event(&Event::PackRequested {
+                    bot: request.bot.clone(),
+                    pack: *package,
+                });
+            }
+        }
+        // Anyone can send us a DCC offer, only those of the bot are considered
+        if let Some(offer) = message.ctcp().filter(|ctcp| ctcp.starts_with("DCC ")) {
+            let reason = if !message.is_from(&request.bot) {
+                Some(format!("not sent by {}", request.bot))
+            } else if message.command != "PRIVMSG" {
+                Some(format!("sent as {}, not PRIVMSG", message.command))
+            } else {
+                None
+            };
+            if let Some(reason) = reason {
+                listener.on_event(&Event::OfferRejected {
+                    sender: message.nick().unwrap_or_default().to_string(),
+                    offer: offer.to_string(),
+                    reason,
+                });
+                continue;
             }
         }
-        if DCC_SEND_REGEX.is_match(&message) {
-            let mut dcc_request = match parse_dcc_send(&message) {
+
+        // Everything else comes from the bot
+        if !is_bot_message(&message, &request.bot, &nickname.current) {
+            continue;
+        }
+        let text = message.last_arg().unwrap_or_default();
+        let ctcp = message.ctcp().unwrap_or_default();
+        if ctcp.is_empty() && packages_requested > 0 {
+            let reason = strip_formatting(text).trim_start_matches('*').trim().to_string();
+            let refusal = match BotReply::parse(text) {
+                Some(BotReply::Queued { position }) => {
+                    queued = true;
+                    listener.on_event(&Event::Queued {
+                        bot: request.bot.clone(),
+                        position,
+                        message: reason.clone(),
+                    });
+                    None
+                }
+                Some(BotReply::InvalidPack) => Some(Error::NotFound(format!(
+                    "{} does not have the requested pack: {}",
+                    request.bot, reason
+                ))),
+                Some(BotReply::AlreadyRequested) => Some(Error::Irc(
+                    format!("{} is already sending to us: {}", request.bot, reason),
+                    None,
+                )),
+                Some(BotReply::QueueFull) => Some(Error::BotUnavailable(format!(
+                    "The queue of {} is full, please try again later: {}",
+                    request.bot, reason
+                ))),
+                Some(BotReply::Denied) => Some(Error::Irc(
+                    format!("{} refused to send the pack: {}", request.bot, reason),
+                    None,
+                )),
+                None => None,
+            };
+            // The request is over, the other packs may still come
+            if let Some(e) = refusal {
+                listener.on_event(&Event::Refused {
+                    bot: request.bot.clone(),
+                    reason,
+                });
+                download_handles.push(thread::spawn(move || Err(e)));
+                continue;
+            }
+        }
+        if ctcp.starts_with("DCC SEND ") {
+            let mut dcc_request = match parse_dcc_send(ctcp) {
                 Some(req) => req,
                 None => {
-                    eprintln!("Warning: Failed to parse DCC SEND message");
+                    listener.on_event(&Event::Warning {
+                        message: "Failed to parse DCC SEND message".to_string(),
+                    });
                     continue;
                 }
             };
+            let reason = if packages_requested == 0 {
+                Some("no package was requested yet")
+            } else if !is_requested_file(&request, &dcc_request.filename) {
+                Some("not a requested file")
+            } else if !offered_files.insert(dcc_request.filename.clone()) {
+                Some("already offered")
+            } else {
+                None
+            };
+            if let Some(reason) = reason {
+                listener.on_event(&Event::OfferRejected {
+                    sender: request.bot.clone(),
+                    offer: ctcp.to_string(),
+                    reason: reason.to_string(),
+                });
+                continue;
+            }
+            listener.on_event(&Event::DccOffer {
+                filename: dcc_request.filename.clone(),
+                size: dcc_request.file_size as u64,
+            });
 
-            // Check if file exists and can be resumed
-            if Path::new(&dcc_request.filename).exists() {
-                let existing_size = std::fs::metadata(&dcc_request.filename)
-                    .map(|m| m.len() as usize)
-                    .unwrap_or(0);
-
-                if existing_size > 0 && existing_size < dcc_request.file_size {
-                    // File is partially downloaded, request resume
-                    dcc_request.resume_position = existing_size;
-
-                    // Quote filename if it contains spaces
-                    let quoted_filename = if dcc_request.filename.contains(' ') {
-                        format!("\"{}\"", dcc_request.filename)
-                    } else {
-                        dcc_request.filename.clone()
-                    };
-
-                    let resume_cmd = format!(
-                        "PRIVMSG {} :\x01DCC RESUME {} {} {}\x01\r\n",
-                        request.bot, quoted_filename, dcc_request.port, existing_size
-                    );
-                    stream.write_all(resume_cmd.as_bytes())
-                        .map_err(|e| format!("Failed to send resume request: {}", e))?;
-
-                    // Store the request and wait for ACCEPT
-                    pending_resumes.insert(dcc_request.port.clone(), dcc_request);
-                    spinner.set_message(format!("Requesting resume from {} bytes...", existing_size));
-                    continue;
-                } else if existing_size >= dcc_request.file_size {
-                    // File is already complete, skip it
-                    spinner.set_message(format!("File {} already complete, skipping", dcc_request.filename));
-                    download_handles.push(thread::spawn(|| Ok(())));
-                    continue;
+            // The name comes from the bot, it must not lead outside of the output directory
+            dcc_request.path = download_path(
+                &request.output_dir,
+                request.subfolder.as_deref(),
+                &dcc_request.filename,
+            );
+
+            dcc_request.bot = request.bot.clone();
+            dcc_request.pack = requested_pack(&request, &dcc_request.filename);
+            dcc_request.on_mismatch = request.on_mismatch;
+            dcc_request.timeouts = request.timeouts;
+            // Bots offer their packs one after the other as slots free up
+            watchdog.restart();
+
+            // Files only get their final name once complete
+            let existing_size = std::fs::metadata(&dcc_request.path).map(|m| m.len() as usize).ok();
+            if existing_size.is_some_and(|size| size >= dcc_request.file_size) {
+                listener.on_event(&Event::AlreadyComplete {
+                    filename: dcc_request.filename.clone(),
+                });
+                download_handles.push(thread::spawn(|| Ok(())));
+                continue;
+            }
+
+            // A partial file is only resumed when its sidecar tells it is the same file
+            let part_size = std::fs::metadata(part_path(&dcc_request.path))
+                .map(|m| m.len() as usize)
+                .unwrap_or(0);
+            let resumable = PartialDownload::load(&dcc_request.path)
+                .is_some_and(|partial| {
+                    partial.size == dcc_request.file_size as u64 && partial.bot.eq_ignore_ascii_case(&request.bot)
+                });
+            if resumable && part_size == dcc_request.file_size {
+                // The transfer ended before the file could be renamed
+                let outcome = anime_verify::file_crc(&part_path(&dcc_request.path))
+                    .and_then(|crc| finish_download(&dcc_request, crc, listener.as_ref()));
+                match &outcome {
+                    Ok(()) => listener.on_event(&Event::AlreadyComplete {
+                        filename: dcc_request.filename.clone(),
+                    }),
+                    Err(e) => listener.on_event(&Event::Failed {
+                        filename: dcc_request.filename.clone(),
+                        error: e.to_string(),
+                    }),
                 }
+                download_handles.push(thread::spawn(move || outcome));
+                continue;
+            }
+            if resumable && part_size > 0 && part_size < dcc_request.file_size {
+                dcc_request.resume_position = part_size;
+
+                let token = dcc_request.token.as_ref().map(|token| format!(" {}", token)).unwrap_or_default();
+                let resume_cmd = format!(
+                    "PRIVMSG {} :\x01DCC RESUME {} {} {}{}\x01\r\n",
+                    request.bot,
+                    quote_filename(&dcc_request.filename),
+                    dcc_request.port,
+                    part_size,
+                    token
+                );
+                stream.write_all(resume_cmd.as_bytes())
+                    .map_err(Error::network("Failed to send resume request"))?;
+
+                // Store the request and wait for ACCEPT
+                listener.on_event(&Event::ResumeRequested {
+                    filename: dcc_request.filename.clone(),
+                    position: part_size as u64,
+                });
+                pending_resumes.insert(dcc_request.resume_key(), dcc_request);
+                continue;
             }
 
             // New download or resume not needed
-            start_download(dcc_request, &mp, &spinner, download_handles.is_empty(), shutdown.clone(), on_start, &mut download_handles);
+            start_download(dcc_request, &request, &mut stream, shutdown.clone(), listener.clone(), &mut download_handles);
         }
-        if DCC_ACCEPT_REGEX.is_match(&message) {
+        if ctcp.starts_with("DCC ACCEPT ") {
             // Resume accepted, start download
-            if let Some(port) = parse_dcc_accept_port(&message) {
-                if let Some(dcc_request) = pending_resumes.remove(&port) {
-                    start_download(dcc_request, &mp, &spinner, download_handles.is_empty(), shutdown.clone(), on_start, &mut download_handles);
+            if let Some(key) = parse_dcc_accept_key(ctcp) {
+                if let Some(dcc_request) = pending_resumes.remove(&key) {
+                    watchdog.restart();
+                    listener.on_event(&Event::ResumeAccepted {
+                        filename: dcc_request.filename.clone(),
+                        position: dcc_request.resume_position as u64,
+                    });
+                    start_download(dcc_request, &request, &mut stream, shutdown.clone(), listener.clone(), &mut download_handles);
                 }
             } else {
-                eprintln!("Warning: Failed to parse DCC ACCEPT message");
+                listener.on_event(&Event::Warning {
+                    message: "Failed to parse DCC ACCEPT message".to_string(),
+                });
             }
         }
     }
     stream
         .write_all("QUIT :my job is done here!\r\n".as_bytes())
         .ok();
-    stream.shutdown(Shutdown::Both).ok();
+    stream.shutdown();
 
     // Wait for all downloads to complete
     // Download threads check shutdown flag themselves, so they'll exit cleanly if interrupted
+    let mut result = Ok(());
     for handle in download_handles {
-        match handle.join() {
-            Ok(Ok(())) => {}, // Download succeeded
-            Ok(Err(e)) => {
-                eprintln!("Download error: {}", e);
+        let outcome = match handle.join() {
+            Ok(outcome) => outcome,
+            Err(_) => Err(Error::Dcc("Download thread panicked".to_string(), None)),
+        };
+        // The first failure is returned, listeners are told about all of them
+        if let Err(e) = outcome {
+            if result.is_ok() {
+                result = Err(e);
+            }
+        }
+    }
+
+    if result.is_ok() && shutdown.load(Ordering::SeqCst) {
+        return Err(Error::Interrupted);
+    }
+    result
+}
+
+/// Asks the bot for its pack list with `xdcc list` and returns it as text.
+/// Bots either answer with one message per pack or send the list as a file over DCC.
+/// The packages of the request are ignored.
+pub fn list_packs(request: &IRCRequest, shutdown: Arc<AtomicBool>) -> Result<String, Error> {
+    let mut has_joined = false;
+    let mut server_ready = false;
+    let mut list_requested = false;
+    let mut pack_list = String::new();
+
+    let mut authentication = Authentication::new(request.credentials.clone());
+    let mut stream = log_in(request, &authentication)?;
+    let mut nickname = Nickname::new(request);
+
+    let mut message_buffer = Vec::new();
+    let mut last_list_line = std::time::Instant::now();
+    let mut watchdog = Watchdog::new(request.timeouts);
+
+    loop {
+        if shutdown.load(Ordering::SeqCst) {
+            stream.write_all("QUIT :Interrupted by user\r\n".as_bytes()).ok();
+            stream.shutdown();
+            return Err(Error::Interrupted);
+        }
+
+        if !pack_list.is_empty() && last_list_line.elapsed() > PACK_LIST_IDLE_TIMEOUT {
+            break;
+        }
+
+        watchdog.enter(if !server_ready || !authentication.is_done() {
+            Stage::Registration
+        } else if !list_requested {
+            Stage::Join
+        } else if pack_list.is_empty() {
+            Stage::Offer
+        } else {
+            // The list flows, however long it takes, until the bot goes quiet
+            Stage::Waiting
+        });
+        if let Err(e) = watchdog.check(&mut stream, request) {
+            stream.write_all("QUIT :Connection timeout\r\n".as_bytes()).ok();
+            stream.shutdown();
+            return Err(e);
+        }
+
+        let line = match read_next_message(&mut stream, &mut message_buffer) {
+            Ok(line) => {
+                watchdog.received();
+                line
+            }
+            Err(e) => {
+                if e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut {
+                    continue;
+                }
+                return Err(Error::network("Lost connection to the IRC server")(e));
             }
-            Err(_) => {
-                eprintln!("Download thread panicked");
+        };
+        let message = match Message::parse(&line) {
+            Some(message) => message,
+            None => continue,
+        };
+
+        if !server_ready {
+            nickname.handle_refusal(&message, request, &mut stream, &|_: &Event| ())?;
+        }
+        if !server_ready && is_motd_end(&message) {
+            server_ready = true;
+        }
+        authentication.handle(&message, server_ready, &mut stream, &|_: &Event| ())?;
+
+        answer_ping(&message, &mut stream)?;
+
+        // Some bots only answer users who are in their channel
+        if server_ready && authentication.is_done() && !has_joined {
+            let channel_join_cmd = format!("JOIN #{}\r\n", request.channel);
+            stream.write_all(channel_join_cmd.as_bytes()).map_err(Error::network("Failed to join channel"))?;
+            has_joined = true;
+        }
+        if is_own_join(&message, &nickname.current, &request.channel) && !list_requested {
+            let xdcc_list_cmd = format!("PRIVMSG {} :xdcc list\r\n", request.bot);
+            stream.write_all(xdcc_list_cmd.as_bytes()).map_err(Error::network("Failed to request pack list"))?;
+            list_requested = true;
+        }
+
+        // Messages before the request are not part of the list
+        if !list_requested || !is_bot_message(&message, &request.bot, &nickname.current) {
+            continue;
+        }
+        if let Some(ctcp) = message.ctcp() {
+            if message.command != "PRIVMSG" || !ctcp.starts_with("DCC SEND ") {
+                continue;
+            }
+            let mut dcc_request = parse_dcc_send(ctcp)
+                .ok_or_else(|| Error::Irc("Failed to parse DCC SEND message".to_string(), None))?;
+            dcc_request.timeouts = request.timeouts;
+            // The size comes from the bot
+            if dcc_request.file_size > MAX_PACK_LIST_SIZE {
+                stream.write_all("QUIT :my job is done here!\r\n".as_bytes()).ok();
+                stream.shutdown();
+                return Err(Error::Dcc(
+                    format!(
+                        "The pack list offered by {} is too large: {} bytes, at most {} are accepted",
+                        request.bot, dcc_request.file_size, MAX_PACK_LIST_SIZE
+                    ),
+                    None,
+                ));
             }
+            if dcc_request.is_passive() {
+                open_passive_dcc(&mut dcc_request, request, &mut stream)?;
+            }
+            let content = match receive_in_memory(&dcc_request, &shutdown) {
+                Ok(Some(content)) => content,
+                Ok(None) => return Err(Error::Interrupted),
+                Err(e) => return Err(Error::dcc("Failed to receive pack list")(e)),
+            };
+            pack_list = String::from_utf8_lossy(&content).to_string();
+            break;
         }
+        pack_list.push_str(message.last_arg().unwrap_or_default());
+        pack_list.push('\n');
+        last_list_line = std::time::Instant::now();
     }
 
+    stream
+        .write_all("QUIT :my job is done here!\r\n".as_bytes())
+        .ok();
+    stream.shutdown();
+    Ok(pack_list)
+}
+
+/// Receives a whole file over DCC, `None` when the user asked to stop meanwhile.
+fn receive_in_memory(request: &DCCSend, shutdown: &AtomicBool) -> Result<Option<Vec<u8>>, std::io::Error> {
+    let mut stream = match open_dcc_stream(request, shutdown)? {
+        Some(stream) => stream,
+        None => return Ok(None),
+    };
+    stream.set_read_timeout(Some(Duration::from_millis(500)))?; // Short timeout to check shutdown flag
+    let mut content = Vec::new();
+    let mut buffer = [0; 4096];
+    let mut last_received = Instant::now();
+    while content.len() < request.file_size {
+        if shutdown.load(Ordering::SeqCst) {
+            stream.shutdown(Shutdown::Both).ok();
+            return Ok(None);
+        }
+        match stream.read(&mut buffer[..]) {
+            Ok(0) => break, // EOF
+            Ok(count) => {
+                last_received = Instant::now();
+                content.extend_from_slice(&buffer[..count]);
+                stream.write_all(&dcc_ack(content.len(), request.file_size)).ok();
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut => {
+                if last_received.elapsed() > request.timeouts.stall {
+                    return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "the transfer stalled"));
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    stream.shutdown(Shutdown::Both).ok();
+    Ok(Some(content))
+}
+
+/// Connects to the bot, or waits for it to connect to us for passive offers. Returns
+/// `None` when the user asks to stop meanwhile.
+fn open_dcc_stream(request: &DCCSend, shutdown: &AtomicBool) -> Result<Option<TcpStream>, std::io::Error> {
+    let listener = match &request.passive_listener {
+        Some(listener) => listener,
+        None => {
+            // Checked by parse_dcc_send
+            let port = request.port.parse::<u16>().unwrap_or_default();
+            let timeout = request.timeouts.connect;
+            let stream = match &request.address {
+                DccAddress::Ip(ip) => TcpStream::connect_timeout(&SocketAddr::new(*ip, port), timeout),
+                DccAddress::Host(host) => connect_tcp(host, port, timeout),
+            };
+            return stream.map(Some);
+        }
+    };
+    // Poll, so that the shutdown flag is checked
+    listener.set_nonblocking(true)?;
+    let start = std::time::Instant::now();
+    loop {
+        if shutdown.load(Ordering::SeqCst) {
+            return Ok(None);
+        }
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false)?;
+                return Ok(Some(stream));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                if start.elapsed() > request.timeouts.connect {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "the bot did not connect to us",
+                    ));
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Answers a passive offer with the address and port we listen on, for the bot to
+/// connect to us. Needed when the bot cannot accept connections itself.
+fn open_passive_dcc(dcc_request: &mut DCCSend, request: &IRCRequest, irc: &mut IrcStream) -> Result<(), Error> {
+    let passive_error = || Error::dcc(format!("Could not accept the passive offer of {}", dcc_request.filename));
+    let ip = match request.external_ip {
+        Some(ip) => ip,
+        None => irc.local_addr().map_err(passive_error())?.ip(),
+    };
+    let unspecified = match ip {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let ports = request.dcc_ports.clone().unwrap_or(0..=0);
+    let listener = ports
+        .clone()
+        .find_map(|port| TcpListener::bind((unspecified, port)).ok())
+        .ok_or_else(|| {
+            Error::Dcc(
+                format!("No free port between {} and {} for passive DCC", ports.start(), ports.end()),
+                None,
+            )
+        })?;
+    let port = listener.local_addr().map_err(passive_error())?.port();
+
+    let reply = format!(
+        "PRIVMSG {} :\x01DCC SEND {} {} {} {} {}\x01\r\n",
+        request.bot,
+        quote_filename(&dcc_request.filename),
+        dcc_address(ip),
+        port,
+        dcc_request.file_size,
+        dcc_request.token.as_deref().unwrap_or_default()
+    );
+    irc.write_all(reply.as_bytes())
+        .map_err(Error::network("Failed to answer the passive DCC offer"))?;
+    dcc_request.passive_listener = Some(listener);
     Ok(())
 }
 
-fn log_in(request: &IRCRequest) -> Result<TcpStream, std::io::Error> {
-    let mut stream = TcpStream::connect(&request.server)?;
-    stream.set_read_timeout(Some(Duration::from_secs(1)))?; // Short timeout to check shutdown flag
-    stream.set_write_timeout(Some(Duration::from_secs(30)))?;
-    stream.write_all(format!("NICK {}\r\n", request.nickname).as_bytes())?;
-    stream.write_all(format!("USER {} 0 * {}\r\n", request.nickname, request.nickname).as_bytes())?;
+/// IPv4 addresses are sent as a 32-bit integer, IPv6 ones as text.
+fn dcc_address(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => u32::from(ip).to_string(),
+        IpAddr::V6(ip) => ip.to_string(),
+    }
+}
+
+/// Filenames with spaces are quoted in DCC messages.
+fn quote_filename(filename: &str) -> String {
+    if filename.contains(' ') {
+        format!("\"{}\"", filename)
+    } else {
+        filename.to_string()
+    }
+}
+
+fn log_in(request: &IRCRequest, authentication: &Authentication) -> Result<IrcStream, Error> {
+    let address = ServerAddress::parse(&request.server, request.tls)?;
+    let mut stream = IrcStream::connect(&address, request.tls_ca.as_deref(), request.tls_pin, request.timeouts.connect)?;
+    let login_error = || Error::network(format!("Failed to log in to {}", request.server));
+    stream.set_read_timeout(Some(Duration::from_secs(1))).map_err(login_error())?; // Short timeout to check shutdown flag
+    stream.set_write_timeout(Some(Duration::from_secs(30))).map_err(login_error())?;
+    authentication.start(&mut stream).map_err(login_error())?;
+    stream.write_all(format!("NICK {}\r\n", request.nickname).as_bytes()).map_err(login_error())?;
+    stream
+        .write_all(format!("USER {} 0 * {}\r\n", request.nickname, request.nickname).as_bytes())
+        .map_err(login_error())?;
     Ok(stream)
 }
 
+/// Reads the next line sent by the server. Bytes are buffered until the line is
+/// complete, so that characters split across reads are decoded whole.
 fn read_next_message(
-    stream: &mut TcpStream,
-    message_builder: &mut String,
+    stream: &mut IrcStream,
+    message_builder: &mut Vec<u8>,
 ) -> Result<String, std::io::Error> {
     let mut buffer = [0; 4];
     const MAX_MESSAGE_SIZE: usize = 4096; // Prevent unbounded growth
 
-    while !message_builder.contains("\n") {
+    while !message_builder.contains(&b'\n') {
         // Prevent DoS from malformed messages
         if message_builder.len() > MAX_MESSAGE_SIZE {
             return Err(std::io::Error::new(
@@ -252,7 +829,7 @@ fn read_next_message(
                 ));
             }
             Ok(count) => {
-                message_builder.push_str(from_utf8(&buffer[..count]).unwrap_or_default());
+                message_builder.extend_from_slice(&buffer[..count]);
             }
             Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {
                 // EINTR - system call was interrupted by a signal, retry
@@ -263,7 +840,7 @@ fn read_next_message(
     }
 
     // We know there's a newline because the loop condition ensures it
-    let endline_offset = match message_builder.find('\n') {
+    let endline_offset = match message_builder.iter().position(|&byte| byte == b'\n') {
         Some(pos) => pos + 1,
         None => return Err(std::io::Error::new(
             std::io::ErrorKind::InvalidData,
@@ -271,17 +848,161 @@ fn read_next_message(
         )),
     };
 
-    let message = message_builder.get(..endline_offset)
-        .ok_or_else(|| std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Internal error: invalid message buffer range"
-        ))?
-        .to_string();
+    // Invalid UTF-8, e.g. from clients using another encoding, only spoils its own characters
+    let line = message_builder.drain(..endline_offset).collect::<Vec<u8>>();
+    Ok(String::from_utf8_lossy(&line).to_string())
+}
+
+/// RPL_ENDOFMOTD or ERR_NOMOTD, the server is ready for commands
+fn is_motd_end(message: &Message) -> bool {
+    message.command == "376" || message.command == "422"
+}
 
-    message_builder.replace_range(..endline_offset, "");
-    Ok(message)
+fn answer_ping(message: &Message, stream: &mut IrcStream) -> Result<(), Error> {
+    if message.command == "PING" {
+        let pong = format!("PONG :{}\r\n", message.last_arg().unwrap_or_default());
+        stream.write_all(pong.as_bytes()).map_err(Error::network("Failed to send PONG"))?;
+    }
+    Ok(())
 }
 
+/// Whether the server confirms that we joined the channel, others join too.
+fn is_own_join(message: &Message, nickname: &str, channel: &str) -> bool {
+    message.command == "JOIN"
+        && message.is_from(nickname)
+        && message
+            .arg(0)
+            .is_some_and(|joined| joined.trim_start_matches('#').eq_ignore_ascii_case(channel))
+}
+
+/// Steps of an IRC session, each with its own timeout.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Stage {
+    /// Until the server welcomed us and we are identified
+    Registration,
+    /// Until the server confirms our JOIN
+    Join,
+    /// Until the bot answers our request
+    Offer,
+    /// In the queue of the bot or while it sends us packs, as long as it takes
+    Waiting,
+}
+
+/// Gives up on the steps of an IRC session which take too long, and PINGs the
+/// server when it has been quiet for a while, so that long waits do not look like a
+/// dead connection to it or to the routers on the way.
+struct Watchdog {
+    timeouts: Timeouts,
+    stage: Stage,
+    stage_start: Instant,
+    last_received: Instant,
+    ping_sent: bool,
+}
+
+impl Watchdog {
+    fn new(timeouts: Timeouts) -> Watchdog {
+        Watchdog {
+            timeouts,
+            stage: Stage::Registration,
+            stage_start: Instant::now(),
+            last_received: Instant::now(),
+            ping_sent: false,
+        }
+    }
+
+    /// Moves to `stage`, whose clock starts unless it is the current one.
+    fn enter(&mut self, stage: Stage) {
+        if stage != self.stage {
+            self.stage = stage;
+            self.stage_start = Instant::now();
+        }
+    }
+
+    /// Starts the clock of the current stage again, e.g. when the bot answers.
+    fn restart(&mut self) {
+        self.stage_start = Instant::now();
+    }
+
+    /// To call for every line received from the server.
+    fn received(&mut self) {
+        self.last_received = Instant::now();
+        self.ping_sent = false;
+    }
+
+    /// Fails when the current stage takes too long or when the server does not
+    /// answer our PING, sends one when the server has been quiet for a while.
+    fn check(&mut self, stream: &mut IrcStream, request: &IRCRequest) -> Result<(), Error> {
+        let quiet = self.last_received.elapsed();
+        if self.ping_sent && quiet > 2 * self.timeouts.ping_interval {
+            return Err(Error::Network(
+                format!("{} stopped answering, the connection was lost", request.server),
+                None,
+            ));
+        }
+        if !self.ping_sent && quiet > self.timeouts.ping_interval {
+            stream.write_all(b"PING :anime-cli\r\n").map_err(Error::network("Failed to send PING"))?;
+            self.ping_sent = true;
+        }
+
+        let timeout = match self.stage {
+            Stage::Registration => self.timeouts.registration,
+            Stage::Join => self.timeouts.join,
+            Stage::Offer => self.timeouts.offer,
+            Stage::Waiting => return Ok(()),
+        };
+        if self.stage_start.elapsed() <= timeout {
+            return Ok(());
+        }
+        let seconds = timeout.as_secs();
+        Err(match self.stage {
+            Stage::Registration => Error::Network(
+                format!("{} did not welcome us within {} seconds. Please try again.", request.server, seconds),
+                None,
+            ),
+            Stage::Join => Error::Irc(format!("Could not join #{} within {} seconds", request.channel, seconds), None),
+            _ => Error::BotUnavailable(format!(
+                "{} did not answer the request within {} seconds, it may be offline",
+                request.bot, seconds
+            )),
+        })
+    }
+}
+
+/// The requested pack of an offered file: the one at the same position as its
+/// filename, or the only requested pack.
+fn requested_pack(request: &IRCRequest, filename: &str) -> Option<i32> {
+    match request.filenames.iter().position(|requested| matches_filename(requested, filename)) {
+        Some(i) => request.packages.get(i).copied(),
+        None if request.packages.len() == 1 => Some(request.packages[0]),
+        None => None,
+    }
+}
+
+/// Whether the offered file is one of the requested ones. Any file is expected when
+/// the filenames are unknown.
+fn is_requested_file(request: &IRCRequest, filename: &str) -> bool {
+    request.filenames.is_empty()
+        || request
+            .filenames
+            .iter()
+            .any(|requested| matches_filename(requested, filename))
+}
+
+/// Whether `offered` is the `requested` filename. Bots may replace spaces with
+/// underscores, and change the case.
+fn matches_filename(requested: &str, offered: &str) -> bool {
+    let normalize = |name: &str| name.replace(' ', "_").to_lowercase();
+    normalize(requested) == normalize(offered)
+}
+
+/// Whether the bot sent `message` to us, and not to a channel it announces in.
+fn is_bot_message(message: &Message, bot: &str, nickname: &str) -> bool {
+    (message.command == "PRIVMSG" || message.command == "NOTICE")
+        && message.is_from(bot)
+        && message.arg(0).is_some_and(|target| target.eq_ignore_ascii_case(nickname))
+}
+
+/// Parses the body of a DCC SEND CTCP message.
 fn parse_dcc_send(message: &str) -> Option<DCCSend> {
     let captures = DCC_SEND_REGEX.captures(message)?;
     // Filename can be in capture group 1 (quoted) or 2 (unquoted)
@@ -290,114 +1011,254 @@ fn parse_dcc_send(message: &str) -> Option<DCCSend> {
         .map(|m| m.as_str())
         .unwrap_or("")
         .to_string();
-    let ip_number = captures[3].parse::<u32>().ok()?;
+    let address = DccAddress::parse(&captures[3])?;
+    captures[4].parse::<u16>().ok()?;
     let file_size = captures[5].parse::<usize>().ok()?;
+    let token = captures.get(6).map(|m| m.as_str().to_string());
+    // Passive offers are useless without their token
+    if &captures[4] == "0" && token.is_none() {
+        return None;
+    }
 
     Some(DCCSend {
         filename,
-        ip: IpAddr::V4(Ipv4Addr::from(ip_number)),
+        path: PathBuf::new(),
+        bot: String::new(),
+        pack: None,
+        on_mismatch: MismatchAction::Keep,
+        address,
         port: captures[4].to_string(),
         file_size,
         resume_position: 0,
+        token,
+        passive_listener: None,
+        timeouts: Timeouts::default(),
     })
 }
 
-fn parse_dcc_accept_port(message: &str) -> Option<String> {
+/// The port of a DCC ACCEPT message, or its token for passive offers, see
+/// [`DCCSend::resume_key`].
+fn parse_dcc_accept_key(message: &str) -> Option<String> {
     let captures = DCC_ACCEPT_REGEX.captures(message)?;
-    Some(captures[3].to_string())
+    match captures.get(5) {
+        Some(token) if &captures[3] == "0" => Some(token.as_str().to_string()),
+        _ => Some(captures[3].to_string()),
+    }
 }
 
 fn start_download(
-    dcc_request: DCCSend,
-    mp: &MultiProgress,
-    spinner: &ProgressBar,
-    is_first: bool,
+    mut dcc_request: DCCSend,
+    request: &IRCRequest,
+    irc: &mut IrcStream,
     shutdown: Arc<AtomicBool>,
-    on_start: fn(String) -> (),
-    download_handles: &mut Vec<std::thread::JoinHandle<std::result::Result<(), std::io::Error>>>,
+    listener: Arc<dyn EventListener>,
+    download_handles: &mut Vec<std::thread::JoinHandle<std::result::Result<(), Error>>>,
 ) {
-    // Clear the spinner once we start downloading
-    if is_first {
-        spinner.finish_and_clear();
+    if dcc_request.is_passive() {
+        if let Err(e) = open_passive_dcc(&mut dcc_request, request, irc) {
+            listener.on_event(&Event::Failed {
+                filename: dcc_request.filename.clone(),
+                error: e.to_string(),
+            });
+            download_handles.push(thread::spawn(move || Err(e)));
+            return;
+        }
     }
-
-    let pb = mp.add(ProgressBar::new(dcc_request.file_size as u64));
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{msg} [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({percent}%) {bytes_per_sec} ETA: {eta}")
-            .unwrap()
-            .progress_chars("#>-")
-    );
-
-    let action = if dcc_request.resume_position > 0 {
-        "Resuming"
-    } else {
-        "Downloading"
-    };
-    pb.set_message(format!("{} {}", action, dcc_request.filename));
-    pb.enable_steady_tick(Duration::from_millis(500));
-
     let handle = thread::spawn(move || {
-        download_file(dcc_request, pb, shutdown, on_start)
+        let filename = dcc_request.filename.clone();
+        let result = download_file(dcc_request, shutdown, listener.as_ref());
+        if let Err(e) = &result {
+            listener.on_event(&Event::Failed {
+                filename,
+                error: e.to_string(),
+            });
+        }
+        result
     });
     download_handles.push(handle);
 }
 
 fn download_file(
     request: DCCSend,
-    progress_bar: ProgressBar,
     shutdown: Arc<AtomicBool>,
-    on_start: fn(String) -> (),
-) -> std::result::Result<(), std::io::Error> {
-    let filename = request.filename.to_string();
+    listener: &dyn EventListener,
+) -> std::result::Result<(), Error> {
+    let write_error = || Error::filesystem(format!("Could not write {}", part_path(&request.path).display()));
+    let transfer_error = || Error::dcc(format!("Transfer of {} failed", request.filename));
 
+    if let Some(directory) = request.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(directory)
+            .map_err(Error::filesystem(format!("Could not create {}", directory.display())))?;
+    }
     // Open file in append mode if resuming, otherwise create new
+    let part = part_path(&request.path);
+    let mut hasher = crc32fast::Hasher::new();
     let mut file = if request.resume_position > 0 {
-        OpenOptions::new()
+        let mut file = OpenOptions::new()
+            .read(true)
             .append(true)
-            .open(&request.filename)?
+            .open(&part)
+            .map_err(Error::filesystem(format!("Could not open {}", part.display())))?;
+        // The checksum covers the part received before
+        std::io::copy(&mut (&mut file).take(request.resume_position as u64), &mut CrcWriter(&mut hasher))
+            .map_err(Error::filesystem(format!("Could not read {}", part.display())))?;
+        file
     } else {
-        File::create(&request.filename)?
+        let partial = PartialDownload {
+            size: request.file_size as u64,
+            bot: request.bot.clone(),
+            pack: request.pack,
+        };
+        partial.save(&request.path)?;
+        File::create(&part)
+            .map_err(Error::filesystem(format!("Could not create {}", part.display())))?
     };
 
-    let mut stream = TcpStream::connect(format!("{}:{}", request.ip, request.port))?;
-    stream.set_read_timeout(Some(Duration::from_millis(500)))?; // Short timeout to check shutdown flag
+    let mut stream = match open_dcc_stream(&request, &shutdown) {
+        Ok(Some(stream)) => stream,
+        Ok(None) => {
+            listener.on_event(&Event::Cancelled {
+                filename: request.filename.clone(),
+            });
+            return Ok(());
+        }
+        Err(e) => {
+            return Err(Error::dcc(format!("Could not connect to the bot to download {}", request.filename))(e))
+        }
+    };
+    stream.set_read_timeout(Some(Duration::from_millis(500))).map_err(transfer_error())?; // Short timeout to check shutdown flag
+    stream.set_write_timeout(Some(Duration::from_secs(30))).map_err(transfer_error())?;
     let mut buffer = [0; 4096];
     let mut progress: usize = request.resume_position;
+    let mut last_received = Instant::now();
 
-    // Set initial progress bar position for resume
-    if request.resume_position > 0 {
-        progress_bar.set_position(request.resume_position as u64);
-    }
-
-    on_start(filename);
+    listener.on_event(&Event::TransferStarted {
+        filename: request.filename.clone(),
+        size: request.file_size as u64,
+        position: request.resume_position as u64,
+    });
 
     while progress < request.file_size {
         // Check for shutdown signal
         if shutdown.load(Ordering::SeqCst) {
-            progress_bar.set_message(format!("✗ Interrupted {}", request.filename));
-            progress_bar.abandon();
+            listener.on_event(&Event::Cancelled {
+                filename: request.filename.clone(),
+            });
             stream.shutdown(Shutdown::Both).ok();
-            file.flush()?;
+            file.flush().map_err(write_error())?;
             return Ok(());
         }
 
         match stream.read(&mut buffer[..]) {
             Ok(count) if count > 0 => {
-                file.write_all(&buffer[..count])?;
+                last_received = Instant::now();
+                file.write_all(&buffer[..count]).map_err(write_error())?;
+                hasher.update(&buffer[..count]);
                 progress += count;
-                progress_bar.set_position(progress as u64);
+                // Many bots wait for the acknowledgement before sending more
+                if let Err(e) = stream.write_all(&dcc_ack(progress, request.file_size)) {
+                    // and some hang up right after the last byte
+                    if progress < request.file_size {
+                        return Err(transfer_error()(e));
+                    }
+                }
+                listener.on_event(&Event::Progress {
+                    filename: request.filename.clone(),
+                    position: progress as u64,
+                    size: request.file_size as u64,
+                });
             }
             Ok(_) => break, // EOF
             Err(e) if e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut => {
+                // The partial file is kept to be resumed later
+                if last_received.elapsed() > request.timeouts.stall {
+                    stream.shutdown(Shutdown::Both).ok();
+                    file.flush().map_err(write_error())?;
+                    return Err(Error::Dcc(
+                        format!(
+                            "The transfer of {} stalled for {} seconds after {} of {} bytes, it can be resumed",
+                            request.filename,
+                            request.timeouts.stall.as_secs(),
+                            progress,
+                            request.file_size
+                        ),
+                        None,
+                    ));
+                }
                 // Timeout, continue to check shutdown flag
                 continue;
             }
-            Err(e) => return Err(e),
+            Err(e) => return Err(transfer_error()(e)),
         }
     }
-    progress_bar.finish_with_message(format!("✓ Downloaded {}", request.filename));
-    stream.shutdown(Shutdown::Both)?;
-    file.flush()?;
+    stream.shutdown(Shutdown::Both).ok();
+    file.flush().map_err(write_error())?;
+    drop(file);
+    // The partial file is kept to be resumed later
+    if progress < request.file_size {
+        return Err(Error::Dcc(
+            format!(
+                "The bot closed the transfer of {} after {} of {} bytes, it can be resumed",
+                request.filename, progress, request.file_size
+            ),
+            None,
+        ));
+    }
+    finish_download(&request, hasher.finalize(), listener)?;
+    listener.on_event(&Event::Completed {
+        filename: request.filename.clone(),
+    });
     Ok(())
 }
+
+/// DCC acknowledgement of the bytes received so far: the position in the file as a
+/// 32-bit big-endian integer, or as a 64-bit one for files over 4 GiB which would
+/// overflow it.
+fn dcc_ack(position: usize, file_size: usize) -> Vec<u8> {
+    if file_size as u64 > u64::from(u32::MAX) {
+        (position as u64).to_be_bytes().to_vec()
+    } else {
+        (position as u32).to_be_bytes().to_vec()
+    }
+}
+
+/// Gives the complete `.part` file of the request its final name, then checks its
+/// `crc` against the filename. Mismatching files fail the download.
+fn finish_download(request: &DCCSend, crc: u32, listener: &dyn EventListener) -> Result<(), Error> {
+    let path = &request.path;
+    std::fs::rename(part_path(path), path)
+        .map_err(Error::filesystem(format!("Could not rename the partial file to {}", path.display())))?;
+    PartialDownload::remove(path);
+
+    match anime_verify::expected_crc(&request.filename).map(|expected| anime_verify::compare(expected, crc)) {
+        Some(Verification::Mismatch { expected, actual }) => {
+            request.on_mismatch.apply(path)?;
+            Err(Error::Corrupted(format!(
+                "{} is corrupted: its CRC32 is {:08X} instead of {:08X}",
+                request.filename, actual, expected
+            )))
+        }
+        Some(Verification::Valid { crc }) => {
+            listener.on_event(&Event::Verified {
+                filename: request.filename.clone(),
+                crc,
+            });
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Feeds what is written to a CRC32 hasher.
+struct CrcWriter<'a>(&'a mut crc32fast::Hasher);
+
+impl Write for CrcWriter<'_> {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        self.0.update(buffer);
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use std::error::Error;
+use std::fmt;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    // ` - 01`, `E01`, `Ep 01` or `Ep.01`, possibly followed by a version such as `v2`
+    static ref EPISODE_REGEX: Regex =
+        Regex::new(r#"(?i)(?: - |\bE|\bEp\.? ?)(\d{1,4})(?:v\d+)?\b"#).unwrap();
+}
+
+/// One comma separated item of an episode specification such as `1,3,7-9,12-`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EpisodeSpec {
+    Single(u16),
+    Range(u16, u16),
+    /// Every available episode starting from this one, e.g. `5-`
+    From(u16),
+    Latest,
+    All,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EpisodeParseError {
+    Empty,
+    InvalidNumber(String),
+    InvalidRange(String),
+}
+
+impl fmt::Display for EpisodeParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EpisodeParseError::Empty => write!(f, "No episode given."),
+            EpisodeParseError::InvalidNumber(e) => write!(
+                f,
+                "'{}' is not a valid episode number. Episode numbers must be positive integers.",
+                e
+            ),
+            EpisodeParseError::InvalidRange(r) => write!(
+                f,
+                "'{}' is not a valid episode range. Ranges look like '1-12' or '5-'.",
+                r
+            ),
+        }
+    }
+}
+
+impl Error for EpisodeParseError {}
+
+/// Finds the episode number in a release filename such as `Title - 01 [720p].mkv`.
+/// Returns where its marker starts, the title being before it, and the number.
+pub fn find_episode(filename: &str) -> Option<(usize, u16)> {
+    let captures = EPISODE_REGEX.captures(filename)?;
+    let start = captures.get(0)?.start();
+    Some((start, captures[1].parse().ok()?))
+}
+
+/// Parses episode specifications like `1,3,7-9`, `5-`, `latest` or `all`.
+pub fn parse_episodes(episodes: &str) -> Result<Vec<EpisodeSpec>, EpisodeParseError> {
+    if episodes.trim().is_empty() {
+        return Err(EpisodeParseError::Empty);
+    }
+    episodes.split(',').map(|e| parse_spec(e.trim())).collect()
+}
+
+fn parse_spec(spec: &str) -> Result<EpisodeSpec, EpisodeParseError> {
+    match spec.to_lowercase().as_str() {
+        "" => return Err(EpisodeParseError::Empty),
+        "latest" => return Ok(EpisodeSpec::Latest),
+        "all" => return Ok(EpisodeSpec::All),
+        _ => {}
+    }
+
+    let (start, end) = match spec.find('-') {
+        Some(pos) => (&spec[..pos], Some(&spec[pos + 1..])),
+        None => return parse_number(spec).map(EpisodeSpec::Single),
+    };
+    let invalid_range = || EpisodeParseError::InvalidRange(spec.to_string());
+    let start = parse_number(start.trim()).map_err(|_| invalid_range())?;
+    match end.map(str::trim) {
+        Some("") => Ok(EpisodeSpec::From(start)),
+        Some(end) => {
+            let end = parse_number(end).map_err(|_| invalid_range())?;
+            if end < start {
+                return Err(invalid_range());
+            }
+            Ok(EpisodeSpec::Range(start, end))
+        }
+        None => Err(invalid_range()),
+    }
+}
+
+fn parse_number(number: &str) -> Result<u16, EpisodeParseError> {
+    number
+        .parse::<u16>()
+        .map_err(|_| EpisodeParseError::InvalidNumber(number.to_string()))
+}
+
+/// Whether the specifications can only be resolved knowing the available episodes.
+pub fn is_open_ended(specs: &[EpisodeSpec]) -> bool {
+    specs.iter().any(|spec| match spec {
+        EpisodeSpec::From(_) | EpisodeSpec::Latest | EpisodeSpec::All => true,
+        EpisodeSpec::Single(_) | EpisodeSpec::Range(_, _) => false,
+    })
+}
+
+/// Expands the specifications into episode numbers, in the given order and without
+/// duplicates. Open-ended forms only yield episodes listed in `available`.
+pub fn resolve_episodes(specs: &[EpisodeSpec], available: &[u16]) -> Vec<u16> {
+    let mut available = available.to_vec();
+    available.sort_unstable();
+    available.dedup();
+
+    let mut episodes = Vec::new();
+    for spec in specs {
+        let expanded = match *spec {
+            EpisodeSpec::Single(e) => vec![e],
+            EpisodeSpec::Range(start, end) => (start..=end).collect(),
+            EpisodeSpec::From(start) => available.iter().cloned().filter(|&e| e >= start).collect(),
+            EpisodeSpec::Latest => available.last().cloned().into_iter().collect(),
+            EpisodeSpec::All => available.clone(),
+        };
+        for episode in expanded {
+            if !episodes.contains(&episode) {
+                episodes.push(episode);
+            }
+        }
+    }
+    episodes
+}
@@ -0,0 +1,116 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::anime_find::DCCPackage;
+
+lazy_static! {
+    static ref TAG_REGEX: Regex = Regex::new(r#"\[([^\]]+)\]|\(([^)]+)\)"#).unwrap();
+    static ref RESOLUTION_REGEX: Regex =
+        Regex::new(r#"(?i)\b(?:\d{3,4}x)?(\d{3,4})p?\b"#).unwrap();
+}
+
+/// Release group and resolution, as announced by the bracketed (or parenthesized)
+/// tags of a filename
+/// such as `[HorribleSubs] Steins Gate 0 - 01 [720p].mkv`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReleaseTags {
+    pub group: Option<String>,
+    pub resolution: Option<String>,
+}
+
+impl ReleaseTags {
+    pub fn parse(filename: &str) -> ReleaseTags {
+        let mut tags = ReleaseTags::default();
+        for captures in TAG_REGEX.captures_iter(filename) {
+            let whole = captures.get(0).unwrap();
+            let tag = match captures.get(1).or_else(|| captures.get(2)) {
+                Some(t) => t.as_str().trim(),
+                None => continue,
+            };
+            if tags.resolution.is_none() {
+                tags.resolution = parse_resolution(tag);
+                if tags.resolution.is_some() {
+                    continue;
+                }
+            }
+            // By convention the group tag is the bracketed one leading the filename
+            if whole.start() == 0 && whole.as_str().starts_with('[') {
+                tags.group = Some(tag.to_string());
+            }
+        }
+        tags
+    }
+}
+
+const KNOWN_HEIGHTS: [&str; 8] = ["360", "480", "540", "576", "720", "1080", "1440", "2160"];
+
+/// Turns `1080p`, `1080` or `1920x1080` into `1080p`.
+fn parse_resolution(tag: &str) -> Option<String> {
+    let captures = RESOLUTION_REGEX.captures(tag)?;
+    let whole = captures.get(0)?.as_str().to_lowercase();
+    let height = &captures[1];
+    // A bare number is only trusted as a resolution when it is a usual height,
+    // so that tags such as `[2019]` are not mistaken for one
+    if !whole.ends_with('p') && !whole.contains('x') && !KNOWN_HEIGHTS.contains(&height) {
+        return None;
+    }
+    Some(format!("{}p", height))
+}
+
+/// User preferences used to narrow down and order search results.
+#[derive(Default)]
+pub struct Preferences {
+    pub resolution: Option<String>,
+    pub group: Option<String>,
+    pub exclude: Vec<String>,
+}
+
+impl Preferences {
+    /// Drops the packages which do not match the preferences.
+    pub fn filter(&self, packages: Vec<DCCPackage>) -> Vec<DCCPackage> {
+        let resolution = self
+            .resolution
+            .as_ref()
+            .map(|r| parse_resolution(r).unwrap_or_else(|| r.to_lowercase()));
+        let excluded = self.exclude.iter().map(|e| e.to_lowercase()).collect::<Vec<_>>();
+
+        packages
+            .into_iter()
+            .filter(|package| {
+                let filename = package.filename.to_lowercase();
+                if excluded.iter().any(|e| filename.contains(e.as_str())) {
+                    return false;
+                }
+                let tags = ReleaseTags::parse(&package.filename);
+                if let Some(group) = &self.group {
+                    match &tags.group {
+                        Some(g) if g.eq_ignore_ascii_case(group) => {}
+                        _ => return false,
+                    }
+                }
+                if let Some(resolution) = &resolution {
+                    match &tags.resolution {
+                        Some(r) if r.eq_ignore_ascii_case(resolution) => {}
+                        _ => return false,
+                    }
+                }
+                true
+            })
+            .collect()
+    }
+}
+
+/// Orders the packages so that the ones from the same release as `reference`
+/// come first: same group and resolution, then same group, then same resolution.
+/// The original order is kept otherwise.
+pub fn rank(packages: &mut [DCCPackage], reference: &DCCPackage) {
+    let reference_tags = ReleaseTags::parse(&reference.filename);
+    packages.sort_by_key(|package| {
+        let tags = ReleaseTags::parse(&package.filename);
+        let same_group = tags.group.is_some() && tags.group == reference_tags.group;
+        let same_resolution =
+            tags.resolution.is_some() && tags.resolution == reference_tags.resolution;
+        let same_bot = package.bot == reference.bot;
+        (!same_group, !same_resolution, !same_bot)
+    });
+}
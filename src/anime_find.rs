@@ -1,203 +1,202 @@
-extern crate reqwest;
-extern crate serde;
-extern crate serde_json;
-
-use reqwest::Error;
 use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::io::{BufReader, Write};
-use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::result::Result;
+use std::thread;
+
+use crate::anime_filter::{self, Preferences};
+use crate::anime_index;
+use crate::error::Error;
+
+mod local;
+mod nibl;
 
-const API_URL: &str = "https://api.nibl.co.uk/nibl";
+pub use local::LocalIndex;
+pub use nibl::{refresh_bot_list, Nibl};
 
+#[derive(Deserialize, Serialize)]
 pub struct DCCPackage {
     pub number: i32,
     pub bot: String,
+    pub filename: String,
+    pub size: String,
+    pub episode: Option<u16>,
 }
 
-pub fn find_packages(query: &String, episode: &Vec<u16>) -> Result<Vec<DCCPackage>, String> {
-    let mut packages = Vec::new();
-    for &ep in episode {
-        match find_package(query, &Some(ep)) {
-            Ok(pkg) => packages.push(pkg),
-            Err(e) => return Err(format!("Episode {}: {}", ep, e)),
-        }
-    }
-    Ok(packages)
+/// Directory holding cached data: `$XDG_CACHE_HOME/anime-cli`, falling back to
+/// `~/.cache/anime-cli` then to the temporary directory.
+pub fn cache_dir() -> PathBuf {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir);
+    base.join("anime-cli")
 }
 
-pub fn find_package(query: &String, episode: &Option<u16>) -> Result<DCCPackage, String> {
-    let packages = match search_packages(query, episode) {
-        Ok(p) => p,
-        Err(_) => {
-            return Err(format!(
-                "Failed to search for '{}'. Please check your internet connection and try again.",
-                query
-            ))
-        }
-    };
-
-    let first_package = match packages.first() {
-        Some(p) => p,
-        None => {
-            let msg = if let Some(ep) = episode {
-                format!(
-                    "No results found for '{}' episode {}. The episode may not exist or may not be available yet.",
-                    query, ep
-                )
-            } else {
-                format!("No results found for '{}'. Please check the title and try again.", query)
-            };
-            return Err(msg);
-        }
-    };
-
-    let bot_name = match find_bot_name(&first_package.bot_id) {
-        Some(b) => b,
-        None => {
-            return Err(format!(
-                "Found results for '{}' but the download bot is not available. Please try again later.",
-                query
-            ))
-        }
-    };
-
-    Ok(DCCPackage {
-        bot: bot_name.to_string(),
-        number: first_package.number,
-    })
+/// A source of XDCC packages, such as a pack index website or a local file.
+/// Providers are shared between threads when searching episodes concurrently.
+pub trait SearchProvider: Sync {
+    /// Returns the packages matching the query, optionally restricted to one episode.
+    /// An empty list means nothing was found.
+    fn search(&self, query: &str, episode: &Option<u16>) -> Result<Vec<DCCPackage>, Error>;
 }
 
-fn search_packages(query: &str, episode: &Option<u16>) -> Result<Vec<Package>, Error> {
-    // URL encode the query to handle special characters safely
-    let encoded_query = urlencoding::encode(query);
-    let mut search_url = format!("{}/search?query={}", API_URL, encoded_query);
-    if let Some(ep) = episode {
-        search_url += &format!("&episodeNumber={}", ep);
+/// Builds the provider selected with `--provider`: `nibl`, `index` (the index
+/// built from bot pack lists) or `file:PATH`.
+pub fn provider_from_name(name: &str) -> Result<Box<dyn SearchProvider>, Error> {
+    match name {
+        "nibl" => return Ok(Box::new(Nibl::new())),
+        "index" => return Ok(Box::new(LocalIndex::new(anime_index::index_path()))),
+        _ => {}
+    }
+    match name.strip_prefix("file:") {
+        Some(path) if !path.is_empty() => Ok(Box::new(LocalIndex::new(path))),
+        _ => Err(Error::InvalidInput(format!(
+            "Unknown provider '{}'. Use 'nibl', 'index' or 'file:PATH'.",
+            name
+        ))),
     }
-
-    // Create client with timeout to prevent hanging
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
-        .build()?;
-
-    let mut response = client.get(&search_url).send()?;
-    let search_result: SearchResult = response.json()?;
-    // Note: API errors are handled by returning an empty content array, not by status field
-    Ok(search_result.content)
-}
-
-fn find_bot_name(id: &i64) -> Option<String> {
-    let bot_list = match get_bot_list() {
-        Ok(list) => list,
-        Err(_) => return None,
-    };
-    let bot = bot_list.iter().find(|bot| &bot.id == id);
-    bot.map(|b| b.name.to_string())
 }
 
-fn get_bot_list() -> Result<Vec<Bot>, String> {
-    let cache_path = get_cache_path();
-    match File::open(&cache_path) {
-        Ok(file) => {
-            let reader = BufReader::new(file);
-            match serde_json::de::from_reader(reader) {
-                Ok(list) => Ok(list),
-                Err(_) => {
-                    // Cache is corrupted, try to fetch fresh data
-                    fetch_and_cache_bot_list(&cache_path)
-                }
-            }
-        }
-        Err(_) => {
-            // Cache doesn't exist, fetch fresh data
-            fetch_and_cache_bot_list(&cache_path)
+/// Finds one package per episode. Episodes after the first one favour the release
+/// (group, resolution and bot) picked for the first, so a season stays consistent.
+pub fn find_packages(
+    provider: &dyn SearchProvider,
+    query: &String,
+    episodes: &[u16],
+    preferences: &Preferences,
+    jobs: usize,
+) -> Result<Vec<DCCPackage>, Error> {
+    let mut packages: Vec<DCCPackage> = Vec::new();
+    for (_, mut candidates) in find_packages_by_episode(provider, query, episodes, preferences, jobs)? {
+        if let Some(reference) = packages.first() {
+            anime_filter::rank(&mut candidates, reference);
         }
+        packages.push(candidates.remove(0));
     }
+    Ok(packages)
 }
 
-fn get_cache_path() -> PathBuf {
-    let mut path = std::env::temp_dir();
-    path.push("animecli.botlist.json");
-    path
-}
-
-fn fetch_and_cache_bot_list(path: &Path) -> Result<Vec<Bot>, String> {
-    // Create client with timeout
-    let client = match reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
-        .build()
-    {
-        Ok(c) => c,
-        Err(_) => return Err("Failed to create HTTP client".to_string()),
-    };
-
-    let mut response = match client.get(&format!("{}/bots", API_URL)).send() {
-        Ok(r) => r,
-        Err(_) => {
-            return Err(
-                "Failed to fetch bot list from server. Please check your internet connection."
-                    .to_string(),
-            )
+/// Finds every package of each episode, in the order of `episodes`.
+/// A single search is made for the whole query and its results are grouped by
+/// episode; only the episodes missing from it are then searched one by one,
+/// running up to `jobs` searches at the same time.
+pub fn find_packages_by_episode(
+    provider: &dyn SearchProvider,
+    query: &String,
+    episodes: &[u16],
+    preferences: &Preferences,
+    jobs: usize,
+) -> Result<Vec<(u16, Vec<DCCPackage>)>, Error> {
+    let mut by_episode: HashMap<u16, Vec<DCCPackage>> = HashMap::new();
+    // Failing here is not fatal, as every episode is then searched on its own
+    if let Ok(packages) = provider.search(query, &None) {
+        for package in preferences.filter(packages) {
+            match package.episode {
+                Some(ep) if episodes.contains(&ep) => by_episode.entry(ep).or_default().push(package),
+                _ => {}
+            }
         }
-    };
+    }
 
-    let bot_list: BotList = match response.json() {
-        Ok(bl) => bl,
-        Err(_) => {
-            return Err("Failed to parse bot list from server. Please try again later.".to_string())
+    let missing = episodes
+        .iter()
+        .cloned()
+        .filter(|ep| !by_episode.contains_key(ep))
+        .collect::<Vec<_>>();
+    for (ep, found) in search_episodes(provider, query, &missing, preferences, jobs) {
+        match found {
+            Ok(pkgs) => {
+                by_episode.insert(ep, pkgs);
+            }
+            Err(e) => return Err(e.prefixed(format!("Episode {}", ep))),
         }
-    };
-
-    if bot_list.status != "OK" {
-        return Err(format!(
-            "Server returned an error: {}. Please try again later.",
-            bot_list.message
-        ));
     }
 
-    // Cache the bot list (ignore errors as cache is optional)
-    let _ = save_bot_list_to_cache(&bot_list, path);
-
-    Ok(bot_list.content)
+    Ok(episodes
+        .iter()
+        .filter_map(|ep| Some((*ep, by_episode.remove(ep)?)))
+        .collect())
 }
 
-fn save_bot_list_to_cache(bot_list: &BotList, path: &Path) -> Result<(), String> {
-    let json_string = match serde_json::to_string(&bot_list.content) {
-        Ok(s) => s,
-        Err(e) => return Err(format!("Could not serialize bot list: {}", e)),
-    };
-    match File::create(path) {
-        Ok(mut file) => match file.write_all(json_string.as_bytes()) {
-            Ok(_) => Ok(()),
-            Err(why) => Err(format!("Could not write bot list to cache: {}", why)),
-        },
-        Err(why) => Err(format!("Could not create cache file: {}", why)),
+fn search_episodes(
+    provider: &dyn SearchProvider,
+    query: &String,
+    episodes: &[u16],
+    preferences: &Preferences,
+    jobs: usize,
+) -> Vec<(u16, Result<Vec<DCCPackage>, Error>)> {
+    let search = |ep: u16| (ep, find_all_packages(provider, query, &Some(ep), preferences));
+    if jobs <= 1 || episodes.len() <= 1 {
+        return episodes.iter().map(|&ep| search(ep)).collect();
     }
-}
 
-#[derive(Deserialize)]
-struct BotList {
-    status: String,
-    message: String,
-    content: Vec<Bot>,
+    let chunk_size = episodes.len().div_ceil(jobs);
+    thread::scope(|scope| {
+        let handles = episodes
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(|&ep| search(ep)).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("Episode search thread panicked"))
+            .collect()
+    })
 }
 
-#[derive(Deserialize, Serialize)]
-struct Bot {
-    id: i64,
-    name: String,
+pub fn find_package(
+    provider: &dyn SearchProvider,
+    query: &String,
+    episode: &Option<u16>,
+    preferences: &Preferences,
+) -> Result<DCCPackage, Error> {
+    let mut packages = find_all_packages(provider, query, episode, preferences)?;
+    Ok(packages.remove(0))
 }
 
-#[derive(Deserialize)]
-struct SearchResult {
-    content: Vec<Package>,
+/// Returns every package matching the query and the preferences, in the order
+/// reported by the provider.
+pub fn find_all_packages(
+    provider: &dyn SearchProvider,
+    query: &String,
+    episode: &Option<u16>,
+    preferences: &Preferences,
+) -> Result<Vec<DCCPackage>, Error> {
+    let packages = provider.search(query, episode)?;
+    if packages.is_empty() {
+        let msg = if let Some(ep) = episode {
+            format!(
+                "No results found for '{}' episode {}. The episode may not exist or may not be available yet.",
+                query, ep
+            )
+        } else {
+            format!("No results found for '{}'. Please check the title and try again.", query)
+        };
+        return Err(Error::NotFound(msg));
+    }
+
+    let packages = preferences.filter(packages);
+    if packages.is_empty() {
+        return Err(Error::NotFound(format!(
+            "Found results for '{}' but none match the requested resolution, group or exclusions.",
+            query
+        )));
+    }
+    Ok(packages)
 }
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct Package {
-    bot_id: i64,
-    number: i32,
+/// Lists the episode numbers the provider reports for the query, in ascending order.
+pub fn available_episodes(
+    provider: &dyn SearchProvider,
+    query: &String,
+    preferences: &Preferences,
+) -> Result<Vec<u16>, Error> {
+    let mut episodes = find_all_packages(provider, query, &None, preferences)?
+        .iter()
+        .filter_map(|package| package.episode)
+        .collect::<Vec<_>>();
+    episodes.sort_unstable();
+    episodes.dedup();
+    Ok(episodes)
 }
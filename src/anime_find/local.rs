@@ -0,0 +1,59 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::result::Result;
+
+use super::{DCCPackage, SearchProvider};
+use crate::error::Error;
+
+/// Searches a JSON file holding a list of packages, without any network access.
+pub struct LocalIndex {
+    path: PathBuf,
+}
+
+impl LocalIndex {
+    pub fn new<P: AsRef<Path>>(path: P) -> LocalIndex {
+        LocalIndex {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    fn load(&self) -> Result<Vec<DCCPackage>, Error> {
+        let file = match File::open(&self.path) {
+            Ok(f) => f,
+            Err(why) => {
+                return Err(Error::Filesystem(
+                    format!("Could not open pack index {}", self.path.display()),
+                    Some(Box::new(why)),
+                ))
+            }
+        };
+        match serde_json::de::from_reader(BufReader::new(file)) {
+            Ok(packages) => Ok(packages),
+            Err(why) => Err(Error::Filesystem(
+                format!("Could not read pack index {}", self.path.display()),
+                Some(Box::new(why)),
+            )),
+        }
+    }
+}
+
+impl SearchProvider for LocalIndex {
+    fn search(&self, query: &str, episode: &Option<u16>) -> Result<Vec<DCCPackage>, Error> {
+        // Every word of the query has to appear in the filename, in any order
+        let words = query
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>();
+        let packages = self
+            .load()?
+            .into_iter()
+            .filter(|package| {
+                let filename = package.filename.to_lowercase();
+                words.iter().all(|word| filename.contains(word.as_str()))
+            })
+            .filter(|package| episode.is_none() || package.episode == *episode)
+            .collect();
+        Ok(packages)
+    }
+}
@@ -0,0 +1,280 @@
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::result::Result;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{DCCPackage, SearchProvider};
+use crate::error::Error;
+
+const API_URL: &str = "https://api.nibl.co.uk/nibl";
+const BOT_LIST_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Searches the NIBL pack index through its HTTP API.
+pub struct Nibl {
+    // Loaded on the first search and kept for the following ones
+    bot_list: Mutex<Option<Vec<Bot>>>,
+    // The bot list is fetched again at most once per run when a bot is unknown
+    refreshed: Mutex<bool>,
+}
+
+impl Nibl {
+    pub fn new() -> Nibl {
+        Nibl {
+            bot_list: Mutex::new(None),
+            refreshed: Mutex::new(false),
+        }
+    }
+
+    fn bot_name(&self, id: i64) -> Result<Option<String>, Error> {
+        let find = |bots: Option<&[Bot]>| {
+            let bot = bots?.iter().find(|bot| bot.id == id)?;
+            Some(bot.name.to_string())
+        };
+        // A poisoned lock only means another search thread panicked, the list is still usable
+        let mut bot_list = self.bot_list.lock().unwrap_or_else(|e| e.into_inner());
+        if bot_list.is_none() {
+            *bot_list = Some(get_bot_list()?);
+        }
+        if let Some(name) = find(bot_list.as_deref()) {
+            return Ok(Some(name));
+        }
+
+        // The bot may be new or renamed since the list was cached
+        let mut refreshed = self.refreshed.lock().unwrap_or_else(|e| e.into_inner());
+        if *refreshed {
+            return Ok(None);
+        }
+        *refreshed = true;
+        *bot_list = Some(fetch_and_cache_bot_list(&get_cache_path())?);
+        Ok(find(bot_list.as_deref()))
+    }
+}
+
+/// Fetches the bot list from the API and caches it, whatever the age of the cache.
+pub fn refresh_bot_list() -> Result<(), Error> {
+    fetch_and_cache_bot_list(&get_cache_path()).map(|_| ())
+}
+
+impl Default for Nibl {
+    fn default() -> Nibl {
+        Nibl::new()
+    }
+}
+
+impl SearchProvider for Nibl {
+    fn search(&self, query: &str, episode: &Option<u16>) -> Result<Vec<DCCPackage>, Error> {
+        let packages = search_packages(query, episode)?;
+        if packages.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut dcc_packages = Vec::new();
+        for package in packages {
+            let bot = match self.bot_name(package.bot_id)? {
+                Some(b) => b,
+                None => continue,
+            };
+            dcc_packages.push(DCCPackage {
+                bot,
+                number: package.number,
+                filename: package.name,
+                size: package.size,
+                // The API reports unknown episode numbers as negative values
+                episode: package.episode_number.and_then(|ep| u16::try_from(ep).ok()),
+            });
+        }
+
+        if dcc_packages.is_empty() {
+            return Err(Error::BotUnavailable(format!(
+                "Found results for '{}' but the download bot is not available. Please try again later.",
+                query
+            )));
+        }
+        Ok(dcc_packages)
+    }
+}
+
+fn search_packages(query: &str, episode: &Option<u16>) -> Result<Vec<Package>, Error> {
+    // URL encode the query to handle special characters safely
+    let encoded_query = urlencoding::encode(query);
+    let mut search_url = format!("{}/search?query={}", API_URL, encoded_query);
+    if let Some(ep) = episode {
+        search_url += &format!("&episodeNumber={}", ep);
+    }
+
+    let network_error = |e: reqwest::Error| {
+        Error::Network(
+            format!(
+                "Failed to search for '{}'. Please check your internet connection and try again.",
+                query
+            ),
+            Some(Box::new(e)),
+        )
+    };
+
+    // Create client with timeout to prevent hanging
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .map_err(network_error)?;
+
+    let mut response = client.get(&search_url).send().map_err(network_error)?;
+    let search_result: SearchResult = response.json().map_err(|e| {
+        Error::Api(
+            format!("Failed to parse search results for '{}'. Please try again later.", query),
+            Some(Box::new(e)),
+        )
+    })?;
+    // Note: API errors are handled by returning an empty content array, not by status field
+    Ok(search_result.content)
+}
+
+fn get_bot_list() -> Result<Vec<Bot>, Error> {
+    let cache_path = get_cache_path();
+    let cache: Option<BotListCache> = File::open(&cache_path)
+        .ok()
+        // A corrupted cache is treated as a missing one
+        .and_then(|file| serde_json::de::from_reader(BufReader::new(file)).ok());
+
+    match cache {
+        Some(cache) if !cache.is_expired() => Ok(cache.bots),
+        _ => fetch_and_cache_bot_list(&cache_path),
+    }
+}
+
+fn get_cache_path() -> PathBuf {
+    let mut path = super::cache_dir();
+    path.push("botlist.json");
+    path
+}
+
+fn fetch_and_cache_bot_list(path: &Path) -> Result<Vec<Bot>, Error> {
+    // Create client with timeout
+    let client = match reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+    {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(Error::Network(
+                "Failed to create HTTP client".to_string(),
+                Some(Box::new(e)),
+            ))
+        }
+    };
+
+    let mut response = match client.get(&format!("{}/bots", API_URL)).send() {
+        Ok(r) => r,
+        Err(e) => {
+            return Err(Error::Network(
+                "Failed to fetch bot list from server. Please check your internet connection."
+                    .to_string(),
+                Some(Box::new(e)),
+            ))
+        }
+    };
+
+    let bot_list: BotList = match response.json() {
+        Ok(bl) => bl,
+        Err(e) => {
+            return Err(Error::Api(
+                "Failed to parse bot list from server. Please try again later.".to_string(),
+                Some(Box::new(e)),
+            ))
+        }
+    };
+
+    if bot_list.status != "OK" {
+        return Err(Error::Api(
+            format!(
+                "Server returned an error: {}. Please try again later.",
+                bot_list.message
+            ),
+            None,
+        ));
+    }
+
+    // Cache the bot list (ignore errors as cache is optional)
+    let _ = save_bot_list_to_cache(&bot_list.content, path);
+
+    Ok(bot_list.content)
+}
+
+fn save_bot_list_to_cache(bots: &[Bot], path: &Path) -> Result<(), String> {
+    let cache = BotListCache {
+        fetched_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        bots: bots.to_vec(),
+    };
+    if let Some(dir) = path.parent() {
+        if let Err(why) = std::fs::create_dir_all(dir) {
+            return Err(format!("Could not create cache directory: {}", why));
+        }
+    }
+    let json_string = match serde_json::to_string(&cache) {
+        Ok(s) => s,
+        Err(e) => return Err(format!("Could not serialize bot list: {}", e)),
+    };
+    match File::create(path) {
+        Ok(mut file) => match file.write_all(json_string.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(why) => Err(format!("Could not write bot list to cache: {}", why)),
+        },
+        Err(why) => Err(format!("Could not create cache file: {}", why)),
+    }
+}
+
+#[derive(Deserialize)]
+struct BotList {
+    status: String,
+    message: String,
+    content: Vec<Bot>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+struct Bot {
+    id: i64,
+    name: String,
+}
+
+#[derive(Deserialize, Serialize)]
+struct BotListCache {
+    /// Seconds since the Unix epoch
+    fetched_at: u64,
+    bots: Vec<Bot>,
+}
+
+impl BotListCache {
+    fn is_expired(&self) -> bool {
+        let fetched_at = UNIX_EPOCH + Duration::from_secs(self.fetched_at);
+        match SystemTime::now().duration_since(fetched_at) {
+            Ok(age) => age > BOT_LIST_TTL,
+            // Fetched in the future: the clock changed, better refresh
+            Err(_) => true,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchResult {
+    content: Vec<Package>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Package {
+    bot_id: i64,
+    number: i32,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    size: String,
+    #[serde(default)]
+    episode_number: Option<i64>,
+}
@@ -0,0 +1,91 @@
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::anime_episodes::find_episode;
+use crate::anime_find::{self, DCCPackage};
+use crate::error::Error;
+use crate::xdcc::strip_formatting;
+
+lazy_static! {
+    // iroffer style line: `#12  3x [700M] [Group] Title - 01 [720p].mkv`
+    static ref PACK_LINE_REGEX: Regex =
+        Regex::new(r#"^#(\d+)\s+\d+x\s+\[\s*([^\]]*?)\s*\]\s+(.+?)\s*$"#).unwrap();
+}
+
+/// Location of the index built from bot pack lists, used by the `index` provider.
+pub fn index_path() -> PathBuf {
+    let mut path = anime_find::cache_dir();
+    path.push("packindex.json");
+    path
+}
+
+/// Parses the answer of a bot to `xdcc list` into packages.
+/// Lines which do not describe a pack (headers, totals...) are skipped.
+pub fn parse_pack_list(bot: &str, pack_list: &str) -> Vec<DCCPackage> {
+    pack_list
+        .lines()
+        .filter_map(|line| {
+            let line = strip_formatting(line);
+            let captures = PACK_LINE_REGEX.captures(line.trim())?;
+            let filename = captures[3].to_string();
+            Some(DCCPackage {
+                number: captures[1].parse().ok()?,
+                bot: bot.to_string(),
+                episode: parse_episode(&filename),
+                size: captures[2].to_string(),
+                filename,
+            })
+        })
+        .collect()
+}
+
+/// Guesses the episode number from filenames like `Title - 01 [720p].mkv`.
+fn parse_episode(filename: &str) -> Option<u16> {
+    find_episode(filename).map(|(_, episode)| episode)
+}
+
+/// Replaces the packages of `bot` in the index with the given ones.
+pub fn update_index(path: &Path, bot: &str, packages: Vec<DCCPackage>) -> Result<(), Error> {
+    let mut index: Vec<DCCPackage> = match File::open(path) {
+        // A corrupted index is simply rebuilt
+        Ok(file) => serde_json::de::from_reader(BufReader::new(file)).unwrap_or_default(),
+        Err(_) => Vec::new(),
+    };
+    index.retain(|package| !package.bot.eq_ignore_ascii_case(bot));
+    index.extend(packages);
+
+    let json_string = match serde_json::to_string(&index) {
+        Ok(s) => s,
+        Err(e) => {
+            return Err(Error::Filesystem(
+                "Could not serialize pack index".to_string(),
+                Some(Box::new(e)),
+            ))
+        }
+    };
+    if let Some(dir) = path.parent() {
+        if let Err(why) = std::fs::create_dir_all(dir) {
+            return Err(Error::Filesystem(
+                "Could not create pack index directory".to_string(),
+                Some(Box::new(why)),
+            ));
+        }
+    }
+    match File::create(path) {
+        Ok(mut file) => match file.write_all(json_string.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(why) => Err(Error::Filesystem(
+                "Could not write pack index".to_string(),
+                Some(Box::new(why)),
+            )),
+        },
+        Err(why) => Err(Error::Filesystem(
+            "Could not create pack index".to_string(),
+            Some(Box::new(why)),
+        )),
+    }
+}
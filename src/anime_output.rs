@@ -0,0 +1,156 @@
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::anime_episodes::find_episode;
+use crate::anime_filter::ReleaseTags;
+use crate::error::Error;
+
+lazy_static! {
+    static ref PLACEHOLDER_REGEX: Regex = Regex::new(r#"\{([^{}]*)\}"#).unwrap();
+    static ref TAG_REGEX: Regex = Regex::new(r#"\[[^\]]*\]|\([^)]*\)"#).unwrap();
+    static ref EXTENSION_REGEX: Regex = Regex::new(r#"\.[[:alnum:]]{1,4}$"#).unwrap();
+}
+
+/// Name given to files whose offered name has nothing usable left
+const DEFAULT_FILENAME: &str = "download";
+/// Value of the placeholders which cannot be found in a filename
+const UNKNOWN: &str = "Unknown";
+const PLACEHOLDERS: [&str; 3] = ["series", "group", "resolution"];
+/// Separators of path components, on Windows as well
+const SEPARATORS: [char; 2] = ['/', '\\'];
+/// Reserved on Windows, and annoying to type anywhere else
+const RESERVED_CHARS: [char; 7] = ['<', '>', ':', '"', '|', '?', '*'];
+
+/// Turns a filename offered by a bot into a safe basename: directories (`../`,
+/// absolute paths), control characters and leading dots are dropped, so that the file
+/// stays in the download directory.
+pub fn sanitize_filename(filename: &str) -> String {
+    let basename = filename.rsplit(SEPARATORS).next().unwrap_or_default();
+    clean_component(basename).unwrap_or_else(|| DEFAULT_FILENAME.to_string())
+}
+
+/// Where to save `filename`: in `output_dir`, within the subfolder described by
+/// `template` if any. The template is a relative path whose `{series}`, `{group}`
+/// and `{resolution}` placeholders are taken from the filename, e.g. `{series}/{resolution}`.
+pub fn download_path(output_dir: &Path, template: Option<&str>, filename: &str) -> PathBuf {
+    let filename = sanitize_filename(filename);
+    let mut path = output_dir.to_path_buf();
+    if let Some(template) = template {
+        let tags = ReleaseTags::parse(&filename);
+        for component in template.split(SEPARATORS) {
+            let expanded = PLACEHOLDER_REGEX.replace_all(component, |captures: &regex::Captures| {
+                let value = match &captures[1] {
+                    "series" => series_name(&filename),
+                    "group" => tags.group.clone(),
+                    "resolution" => tags.resolution.clone(),
+                    _ => None,
+                };
+                value.unwrap_or_else(|| UNKNOWN.to_string())
+            });
+            if let Some(folder) = clean_component(&expanded) {
+                path.push(folder);
+            }
+        }
+    }
+    path.push(filename);
+    path
+}
+
+/// What is known about an unfinished download, kept next to its `.part` file so
+/// that it is only resumed for the same offer.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PartialDownload {
+    /// Size of the complete file, in bytes
+    pub size: u64,
+    pub bot: String,
+    /// Pack number, when the offer could be matched with a requested pack
+    pub pack: Option<i32>,
+}
+
+impl PartialDownload {
+    /// Reads the sidecar of the `.part` file of `path`. A missing or unreadable
+    /// sidecar means the partial file cannot be trusted.
+    pub fn load(path: &Path) -> Option<PartialDownload> {
+        let text = std::fs::read_to_string(sidecar_path(path)).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let sidecar = sidecar_path(path);
+        let json = serde_json::to_string(self)
+            .map_err(|e| Error::Filesystem("Could not serialize download state".to_string(), Some(Box::new(e))))?;
+        std::fs::write(&sidecar, json)
+            .map_err(Error::filesystem(format!("Could not write {}", sidecar.display())))
+    }
+
+    /// Removes the sidecar of `path` once its download is over.
+    pub fn remove(path: &Path) {
+        std::fs::remove_file(sidecar_path(path)).ok();
+    }
+}
+
+/// Where `path` is written until the whole file has arrived: `path` followed by `.part`.
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// `path` followed by `.part.json`.
+fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = part_path(path).into_os_string();
+    sidecar.push(".json");
+    PathBuf::from(sidecar)
+}
+
+/// Checks that a subfolder template only uses known placeholders.
+pub fn check_template(template: &str) -> Result<(), Error> {
+    for captures in PLACEHOLDER_REGEX.captures_iter(template) {
+        if !PLACEHOLDERS.contains(&&captures[1]) {
+            return Err(Error::InvalidInput(format!(
+                "unknown placeholder '{}' in '{}', expected {{series}}, {{group}} or {{resolution}}",
+                &captures[0], template
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The title of the series in a filename such as `[Group] Steins Gate 0 - 01 [720p].mkv`,
+/// i.e. what remains once the tags, the episode and the extension are removed.
+pub fn series_name(filename: &str) -> Option<String> {
+    // Bots often replace spaces with underscores
+    let name = filename.replace('_', " ");
+    let name = EXTENSION_REGEX.replace(&name, "");
+    let name = TAG_REGEX.replace_all(&name, " ");
+    let title = match find_episode(&name) {
+        Some((start, _)) => &name[..start],
+        None => &name,
+    };
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    let title = title.trim_end_matches(|c: char| c == '-' || c.is_whitespace());
+    if title.is_empty() {
+        None
+    } else {
+        Some(title.to_string())
+    }
+}
+
+/// Drops control characters, replaces reserved ones and trims the dots and spaces
+/// which would hide the file, or make it `.` or `..`.
+fn clean_component(name: &str) -> Option<String> {
+    let cleaned = name
+        .chars()
+        .filter(|c| !c.is_control())
+        .map(|c| if RESERVED_CHARS.contains(&c) { '_' } else { c })
+        .collect::<String>();
+    let cleaned = cleaned.trim_matches(|c: char| c == '.' || c.is_whitespace());
+    if cleaned.is_empty() {
+        None
+    } else {
+        Some(cleaned.to_string())
+    }
+}
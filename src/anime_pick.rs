@@ -0,0 +1,112 @@
+use std::io::{BufRead, Write};
+
+use anime_cli::anime_find::DCCPackage;
+use anime_cli::error::Error;
+
+/// Lists the packages on stdout and lets the user pick one or several of them.
+/// An empty answer selects the first package.
+pub fn pick_packages(packages: Vec<DCCPackage>) -> Result<Vec<DCCPackage>, Error> {
+    print_packages(&packages);
+
+    let stdin = std::io::stdin();
+    let mut input = stdin.lock();
+    loop {
+        print!("Select package(s) to download, separated with comma [1]: ");
+        std::io::stdout().flush().ok();
+
+        let mut line = String::new();
+        match input.read_line(&mut line) {
+            Ok(0) => return Err(Error::InvalidInput("No package selected.".to_string())),
+            Ok(_) => {}
+            Err(e) => {
+                return Err(Error::InvalidInput(format!("Failed to read selection: {}", e)))
+            }
+        }
+
+        match parse_selection(line.trim(), packages.len()) {
+            Ok(indexes) => return Ok(select(packages, indexes)),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
+/// The packages at `indexes`, each once even when picked several times.
+fn select(packages: Vec<DCCPackage>, indexes: Vec<usize>) -> Vec<DCCPackage> {
+    let mut packages = packages.into_iter().map(Some).collect::<Vec<_>>();
+    indexes
+        .into_iter()
+        .filter_map(|i| packages[i].take())
+        .collect()
+}
+
+fn print_packages(packages: &[DCCPackage]) {
+    for (i, package) in packages.iter().enumerate() {
+        println!(
+            "{:>3}) {} [{}] #{} {}",
+            i + 1,
+            package.filename,
+            package.size,
+            package.number,
+            package.bot
+        );
+    }
+}
+
+/// Parses a comma separated list of 1-based choices into 0-based indexes.
+fn parse_selection(selection: &str, count: usize) -> Result<Vec<usize>, String> {
+    if selection.is_empty() {
+        return Ok(vec![0]);
+    }
+    selection
+        .split(',')
+        .map(|choice| match choice.trim().parse::<usize>() {
+            Ok(n) if n >= 1 && n <= count => Ok(n - 1),
+            _ => Err(format!(
+                "'{}' is not a valid choice. Please enter numbers between 1 and {}.",
+                choice.trim(),
+                count
+            )),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(number: i32) -> DCCPackage {
+        DCCPackage {
+            number,
+            bot: "Bot".to_string(),
+            filename: format!("{}.mkv", number),
+            size: "1M".to_string(),
+            episode: None,
+        }
+    }
+
+    #[test]
+    fn selects_the_first_package_by_default() {
+        assert_eq!(parse_selection("", 3), Ok(vec![0]));
+    }
+
+    #[test]
+    fn parses_several_choices() {
+        assert_eq!(parse_selection("3, 1", 3), Ok(vec![2, 0]));
+    }
+
+    #[test]
+    fn rejects_choices_out_of_range() {
+        assert!(parse_selection("0", 3).is_err());
+        assert!(parse_selection("4", 3).is_err());
+        assert!(parse_selection("1,", 3).is_err());
+        assert!(parse_selection("one", 3).is_err());
+    }
+
+    #[test]
+    fn selects_repeated_choices_once() {
+        let indexes = parse_selection("1,1", 3).unwrap();
+        let selected = select(vec![package(1), package(2), package(3)], indexes);
+
+        assert_eq!(selected.iter().map(|package| package.number).collect::<Vec<_>>(), vec![1]);
+    }
+}
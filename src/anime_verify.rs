@@ -0,0 +1,105 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::error::Error;
+
+lazy_static! {
+    static ref CRC_REGEX: Regex = Regex::new(r#"[\[(]([[:xdigit:]]{8})[\])]"#).unwrap();
+}
+
+/// What to do with a downloaded file which does not match its CRC32.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MismatchAction {
+    /// Leave it under its name, only the download fails
+    Keep,
+    Delete,
+    /// Rename it to `NAME.corrupt`
+    Rename,
+}
+
+impl MismatchAction {
+    pub fn parse(name: &str) -> Result<MismatchAction, Error> {
+        match name {
+            "keep" => Ok(MismatchAction::Keep),
+            "delete" => Ok(MismatchAction::Delete),
+            "rename" => Ok(MismatchAction::Rename),
+            _ => Err(Error::InvalidInput(format!(
+                "invalid mismatch action '{}', expected keep, delete or rename",
+                name
+            ))),
+        }
+    }
+
+    /// Applies the action to the corrupted file at `path`.
+    pub fn apply(&self, path: &Path) -> Result<(), Error> {
+        match self {
+            MismatchAction::Keep => Ok(()),
+            MismatchAction::Delete => std::fs::remove_file(path)
+                .map_err(Error::filesystem(format!("Could not delete {}", path.display()))),
+            MismatchAction::Rename => std::fs::rename(path, corrupt_path(path))
+                .map_err(Error::filesystem(format!("Could not rename {}", path.display()))),
+        }
+    }
+}
+
+/// Outcome of checking a file against the CRC32 of its filename.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verification {
+    Valid { crc: u32 },
+    Mismatch { expected: u32, actual: u32 },
+    /// The filename has no CRC32 tag
+    Unknown,
+}
+
+/// The CRC32 which fansub releases put in their filename, e.g. `[ABCD1234]` in
+/// `[Group] Show - 01 [720p][ABCD1234].mkv`. The last tag is taken.
+pub fn expected_crc(filename: &str) -> Option<u32> {
+    let captures = CRC_REGEX.captures_iter(filename).last()?;
+    u32::from_str_radix(&captures[1], 16).ok()
+}
+
+/// Computes the CRC32 of a whole file.
+pub fn file_crc(path: &Path) -> Result<u32, Error> {
+    let read_error = || Error::filesystem(format!("Could not read {}", path.display()));
+    let mut file = File::open(path).map_err(read_error())?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer).map_err(read_error())? {
+            0 => break,
+            count => hasher.update(&buffer[..count]),
+        }
+    }
+    Ok(hasher.finalize())
+}
+
+/// Compares the CRC32 of the file at `path` with the one of its filename.
+pub fn verify_file(path: &Path) -> Result<Verification, Error> {
+    std::fs::metadata(path).map_err(Error::filesystem(format!("Could not read {}", path.display())))?;
+    let filename = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let expected = match expected_crc(&filename) {
+        Some(crc) => crc,
+        None => return Ok(Verification::Unknown),
+    };
+    Ok(compare(expected, file_crc(path)?))
+}
+
+/// Whether `actual` is the `expected` CRC32.
+pub fn compare(expected: u32, actual: u32) -> Verification {
+    if expected == actual {
+        Verification::Valid { crc: actual }
+    } else {
+        Verification::Mismatch { expected, actual }
+    }
+}
+
+/// `path` followed by `.corrupt`.
+pub fn corrupt_path(path: &Path) -> PathBuf {
+    let mut corrupt = path.as_os_str().to_owned();
+    corrupt.push(".corrupt");
+    PathBuf::from(corrupt)
+}
@@ -0,0 +1,205 @@
+use std::io::Write;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::connection::IrcStream;
+use crate::error::Error;
+use crate::events::{Event, EventListener};
+use crate::irc::Message;
+
+lazy_static! {
+    static ref NICKSERV_SUCCESS_REGEX: Regex =
+        Regex::new(r#"(?i)password accepted|you are now (identified|recognized|logged in)"#).unwrap();
+    static ref NICKSERV_FAILURE_REGEX: Regex =
+        Regex::new(r#"(?i)invalid password|password incorrect|is(n't| not) registered"#).unwrap();
+}
+
+/// SASL sends its payload in chunks of this size
+const AUTHENTICATE_CHUNK_SIZE: usize = 400;
+const RPL_LOGGEDIN: &str = "900";
+const RPL_SASLSUCCESS: &str = "903";
+/// ERR_NICKLOCKED, ERR_SASLFAIL, ERR_SASLTOOLONG, ERR_SASLABORTED and ERR_SASLALREADY
+const SASL_FAILURES: [&str; 5] = ["902", "904", "905", "906", "907"];
+
+/// Account to identify with, through SASL or NickServ.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Credentials {
+    pub account: String,
+    pub password: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    /// Waiting for the capabilities of the server
+    Negotiating { sasl_offered: bool },
+    /// SASL PLAIN is in progress
+    Authenticating,
+    /// No SASL, NickServ is asked once the server has welcomed us
+    NickServ,
+    /// IDENTIFY was sent to NickServ, waiting for its answer
+    Identifying,
+    Done,
+}
+
+/// Logs in to the account of the request while registering with the server: with
+/// SASL PLAIN when the server supports it, falling back to NickServ IDENTIFY.
+pub struct Authentication {
+    credentials: Option<Credentials>,
+    state: State,
+}
+
+impl Authentication {
+    pub fn new(credentials: Option<Credentials>) -> Authentication {
+        let state = match credentials {
+            Some(_) => State::Negotiating { sasl_offered: false },
+            None => State::Done,
+        };
+        Authentication { credentials, state }
+    }
+
+    /// Sent before NICK and USER, so that the server waits for the end of the
+    /// capability negotiation before welcoming us.
+    pub fn start(&self, stream: &mut IrcStream) -> std::io::Result<()> {
+        if self.credentials.is_some() {
+            stream.write_all(b"CAP LS 302\r\n")?;
+        }
+        Ok(())
+    }
+
+    /// Whether channels can be joined, i.e. there is no account or we are logged in.
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Follows the login through the CAP, AUTHENTICATE and NickServ messages.
+    /// `registered` tells whether the server has finished welcoming us.
+    pub fn handle(
+        &mut self,
+        message: &Message,
+        registered: bool,
+        stream: &mut IrcStream,
+        listener: &dyn EventListener,
+    ) -> Result<(), Error> {
+        let credentials = match &self.credentials {
+            Some(credentials) => credentials,
+            None => return Ok(()),
+        };
+        let send = |stream: &mut IrcStream, command: &str| {
+            stream
+                .write_all(format!("{}\r\n", command).as_bytes())
+                .map_err(Error::network("Failed to authenticate"))
+        };
+
+        match self.state {
+            State::Negotiating { sasl_offered } => {
+                if message.command == "CAP" {
+                    // CAP <target> <subcommand> [*] :<capabilities>
+                    let capabilities = message.last_arg().unwrap_or_default();
+                    match message.arg(1).unwrap_or_default() {
+                        "LS" => {
+                            let sasl_offered = sasl_offered || offers_sasl_plain(capabilities);
+                            if message.params.get(2).is_some_and(|more| more == "*") {
+                                // More capabilities are coming
+                                self.state = State::Negotiating { sasl_offered };
+                            } else if sasl_offered {
+                                send(stream, "CAP REQ :sasl")?;
+                            } else {
+                                send(stream, "CAP END")?;
+                                self.state = State::NickServ;
+                            }
+                        }
+                        "ACK" if capabilities.split_whitespace().any(|c| c == "sasl") => {
+                            send(stream, "AUTHENTICATE PLAIN")?;
+                            self.state = State::Authenticating;
+                        }
+                        _ => {
+                            send(stream, "CAP END")?;
+                            self.state = State::NickServ;
+                        }
+                    }
+                } else if registered {
+                    // The server does not know about capabilities
+                    self.state = State::NickServ;
+                }
+            }
+            State::Authenticating => {
+                if message.command == "AUTHENTICATE" && message.arg(0) == Some("+") {
+                    for chunk in sasl_plain_payload(credentials) {
+                        send(stream, &format!("AUTHENTICATE {}", chunk))?;
+                    }
+                } else if message.command == RPL_SASLSUCCESS {
+                    send(stream, "CAP END")?;
+                    self.state = State::Done;
+                    listener.on_event(&Event::Authenticated {
+                        account: credentials.account.clone(),
+                    });
+                } else if SASL_FAILURES.contains(&message.command.as_str()) {
+                    send(stream, "CAP END")?;
+                    self.state = State::NickServ;
+                    listener.on_event(&Event::Warning {
+                        message: "SASL authentication failed, trying NickServ".to_string(),
+                    });
+                }
+            }
+            State::NickServ | State::Identifying => {}
+            State::Done => return Ok(()),
+        }
+
+        if self.state == State::NickServ && registered {
+            send(
+                stream,
+                &format!("PRIVMSG NickServ :IDENTIFY {} {}", credentials.account, credentials.password),
+            )?;
+            self.state = State::Identifying;
+        } else if self.state == State::Identifying {
+            let from_nickserv = message.command == "NOTICE" && message.is_from("NickServ");
+            let text = message.last_arg().unwrap_or_default();
+            if from_nickserv && NICKSERV_FAILURE_REGEX.is_match(text) {
+                return Err(Error::Irc(
+                    format!("NickServ refused to identify {}: {}", credentials.account, text.trim()),
+                    None,
+                ));
+            }
+            // Some networks confirm with RPL_LOGGEDIN
+            let logged_in = (from_nickserv && NICKSERV_SUCCESS_REGEX.is_match(text))
+                || message.command == RPL_LOGGEDIN;
+            if logged_in {
+                self.state = State::Done;
+                listener.on_event(&Event::Authenticated {
+                    account: credentials.account.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the advertised capabilities include SASL with the PLAIN mechanism.
+/// Servers may only advertise `sasl`, without their mechanisms.
+fn offers_sasl_plain(capabilities: &str) -> bool {
+    capabilities.split_whitespace().any(|capability| {
+        capability == "sasl"
+            || capability
+                .strip_prefix("sasl=")
+                .is_some_and(|mechanisms| mechanisms.split(',').any(|m| m.eq_ignore_ascii_case("PLAIN")))
+    })
+}
+
+/// The base64 `account\0account\0password`, split in AUTHENTICATE chunks. A payload
+/// filling the last chunk is followed by an empty one.
+fn sasl_plain_payload(credentials: &Credentials) -> Vec<String> {
+    let payload = base64::encode(&format!(
+        "{}\0{}\0{}",
+        credentials.account, credentials.account, credentials.password
+    ));
+    let mut chunks = payload
+        .as_bytes()
+        .chunks(AUTHENTICATE_CHUNK_SIZE)
+        .map(|chunk| String::from_utf8_lossy(chunk).to_string())
+        .collect::<Vec<_>>();
+    if payload.len() % AUTHENTICATE_CHUNK_SIZE == 0 {
+        chunks.push("+".to_string());
+    }
+    chunks
+}
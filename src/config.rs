@@ -0,0 +1,376 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::anime_output::check_template;
+use crate::anime_verify::MismatchAction;
+use crate::auth::Credentials;
+use crate::error::Error;
+
+const DEFAULT_SERVER: &str = "irc.rizon.net:6667";
+const DEFAULT_CHANNEL: &str = "nibl";
+const DEFAULT_NICKNAME: &str = "randomRustacean";
+/// Prefix of the environment variables overriding the config file, e.g. `ANIME_CLI_NICKNAME`
+const ENV_PREFIX: &str = "ANIME_CLI_";
+const KEYS: [&str; 21] = [
+    "server",
+    "channel",
+    "nickname",
+    "nick_strategy",
+    "nick_attempts",
+    "tls",
+    "tls_ca",
+    "tls_pin",
+    "account",
+    "password",
+    "output_dir",
+    "subfolder",
+    "on_mismatch",
+    "dcc_ports",
+    "external_ip",
+    "connect_timeout",
+    "registration_timeout",
+    "join_timeout",
+    "offer_timeout",
+    "stall_timeout",
+    "ping_interval",
+];
+
+/// Settings of the IRC connection. They come from, by increasing priority: the
+/// defaults, the config file, the `ANIME_CLI_*` environment variables and the
+/// command line.
+///
+/// The config file holds one `key = value` per line, lines starting with `#` are comments:
+/// ```text
+/// server = ircs://irc.rizon.net
+/// channel = nibl
+/// nickname = someRustacean
+/// nick_strategy = number
+/// nick_attempts = 5
+/// tls = true
+/// tls_ca = /etc/ssl/private-ca.pem
+/// tls_pin = true
+/// account = someRustacean
+/// password = hunter2
+/// output_dir = /home/someone/Anime
+/// subfolder = {series}
+/// on_mismatch = rename
+/// dcc_ports = 49152-49159
+/// external_ip = 203.0.113.7
+/// offer_timeout = 120
+/// ping_interval = 90
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub server: String,
+    /// Without the leading `#`
+    pub channel: String,
+    pub nickname: String,
+    /// How to pick another nickname when the server refuses ours
+    pub nick_strategy: NickStrategy,
+    /// Alternative nicknames to try before giving up
+    pub nick_attempts: u32,
+    pub tls: bool,
+    pub tls_ca: Option<PathBuf>,
+    /// Trust only `tls_ca`, not the system certificates
+    pub tls_pin: bool,
+    /// Account to identify with, the nickname by default
+    pub account: Option<String>,
+    pub password: Option<String>,
+    /// Where downloads are saved, the current directory by default
+    pub output_dir: Option<PathBuf>,
+    /// Subfolder template of each download, see [`download_path`](crate::anime_output::download_path)
+    pub subfolder: Option<String>,
+    /// What to do with downloads which do not match the CRC32 of their filename
+    pub on_mismatch: MismatchAction,
+    /// Local ports to listen on for passive DCC offers, e.g. `49152-49159`
+    pub dcc_ports: Option<RangeInclusive<u16>>,
+    /// Public address given to bots making passive DCC offers, when behind NAT
+    pub external_ip: Option<IpAddr>,
+    /// How long each step of a download may take, set in seconds
+    pub timeouts: Timeouts,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            server: DEFAULT_SERVER.to_string(),
+            channel: DEFAULT_CHANNEL.to_string(),
+            nickname: random_nickname(),
+            nick_strategy: NickStrategy::Underscore,
+            nick_attempts: 3,
+            tls: false,
+            tls_ca: None,
+            tls_pin: false,
+            account: None,
+            password: None,
+            output_dir: None,
+            subfolder: None,
+            on_mismatch: MismatchAction::Keep,
+            dcc_ports: None,
+            external_ip: None,
+            timeouts: Timeouts::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the defaults, then the config file when there is one, then the environment.
+    /// `path` replaces the default location of the config file, which must then exist.
+    pub fn load(path: Option<&Path>) -> Result<Config, Error> {
+        let mut config = Config::default();
+        match path {
+            Some(path) => config.apply_file(path)?,
+            None => {
+                if let Some(path) = config_path().filter(|path| path.exists()) {
+                    config.apply_file(&path)?;
+                }
+            }
+        }
+        config.apply_vars(std::env::vars())?;
+        Ok(config)
+    }
+
+    pub fn apply_file(&mut self, path: &Path) -> Result<(), Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(Error::filesystem(format!("Could not read config file {}", path.display())))?;
+        self.apply_str(&text)
+            .map_err(|e| e.prefixed(format!("Invalid config file {}", path.display())))
+    }
+
+    /// Applies the `key = value` lines of a config file.
+    pub fn apply_str(&mut self, text: &str) -> Result<(), Error> {
+        for (number, line) in text.lines().enumerate() {
+            // Channels start with a hash too, so only whole lines are comments
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match line.find('=') {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None => {
+                    return Err(Error::InvalidInput(format!(
+                        "line {}: expected 'key = value'",
+                        number + 1
+                    )))
+                }
+            };
+            self.set(key, value)
+                .map_err(|e| e.prefixed(format!("line {}", number + 1)))?;
+        }
+        Ok(())
+    }
+
+    /// Applies the `ANIME_CLI_*` variables among `vars`, e.g. `ANIME_CLI_SERVER`.
+    pub fn apply_vars<I: IntoIterator<Item = (String, String)>>(&mut self, vars: I) -> Result<(), Error> {
+        for (name, value) in vars {
+            if let Some(key) = name.strip_prefix(ENV_PREFIX) {
+                // Other programs may use the same prefix, only known keys are read
+                if KEYS.contains(&key.to_lowercase().as_str()) {
+                    self.set(&key.to_lowercase(), &value).map_err(|e| e.prefixed(&name))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets one setting from its config file key.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        let non_empty = |value: &str| {
+            if value.is_empty() || value.contains(char::is_whitespace) {
+                Err(Error::InvalidInput(format!("invalid {} '{}'", key, value)))
+            } else {
+                Ok(value.to_string())
+            }
+        };
+        // Paths and passwords may contain spaces
+        let any_text = |value: &str| {
+            if value.is_empty() {
+                Err(Error::InvalidInput(format!("invalid {} ''", key)))
+            } else {
+                Ok(value.to_string())
+            }
+        };
+        match key {
+            "server" => self.server = non_empty(value)?,
+            "channel" => self.channel = non_empty(value.trim_start_matches('#'))?,
+            "nickname" => self.nickname = non_empty(value)?,
+            "nick_strategy" => self.nick_strategy = NickStrategy::parse(value)?,
+            "nick_attempts" => {
+                self.nick_attempts = value
+                    .parse()
+                    .map_err(|_| Error::InvalidInput(format!("invalid nick_attempts '{}'", value)))?
+            }
+            "tls" => self.tls = parse_bool(key, value)?,
+            "tls_ca" => self.tls_ca = Some(PathBuf::from(any_text(value)?)),
+            "tls_pin" => self.tls_pin = parse_bool(key, value)?,
+            "account" => self.account = Some(non_empty(value)?),
+            "password" => self.password = Some(any_text(value)?),
+            "output_dir" => self.output_dir = Some(PathBuf::from(any_text(value)?)),
+            "subfolder" => {
+                check_template(value)?;
+                self.subfolder = Some(any_text(value)?)
+            }
+            "on_mismatch" => self.on_mismatch = MismatchAction::parse(value)?,
+            "dcc_ports" => self.dcc_ports = Some(parse_port_range(value)?),
+            "external_ip" => {
+                self.external_ip = Some(
+                    value
+                        .parse()
+                        .map_err(|_| Error::InvalidInput(format!("invalid external_ip '{}'", value)))?,
+                )
+            }
+            "connect_timeout" => self.timeouts.connect = parse_seconds(key, value)?,
+            "registration_timeout" => self.timeouts.registration = parse_seconds(key, value)?,
+            "join_timeout" => self.timeouts.join = parse_seconds(key, value)?,
+            "offer_timeout" => self.timeouts.offer = parse_seconds(key, value)?,
+            "stall_timeout" => self.timeouts.stall = parse_seconds(key, value)?,
+            "ping_interval" => self.timeouts.ping_interval = parse_seconds(key, value)?,
+            _ => return Err(Error::InvalidInput(format!("unknown setting '{}'", key))),
+        }
+        Ok(())
+    }
+
+    /// The account to identify with, when a password is set.
+    pub fn credentials(&self) -> Option<Credentials> {
+        let password = self.password.clone()?;
+        Some(Credentials {
+            account: self.account.clone().unwrap_or_else(|| self.nickname.clone()),
+            password,
+        })
+    }
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, Error> {
+    match value {
+        "true" | "yes" | "1" => Ok(true),
+        "false" | "no" | "0" => Ok(false),
+        _ => Err(Error::InvalidInput(format!("invalid {} '{}', expected true or false", key, value))),
+    }
+}
+
+/// Parses `port` or `first-last`.
+fn parse_port_range(value: &str) -> Result<RangeInclusive<u16>, Error> {
+    let invalid = || {
+        Error::InvalidInput(format!(
+            "invalid dcc_ports '{}', expected a port or a range such as 49152-49159",
+            value
+        ))
+    };
+    let (first, last) = match value.find('-') {
+        Some(i) => (&value[..i], &value[i + 1..]),
+        None => (value, value),
+    };
+    let first = first.trim().parse::<u16>().map_err(|_| invalid())?;
+    let last = last.trim().parse::<u16>().map_err(|_| invalid())?;
+    if first == 0 || first > last {
+        return Err(invalid());
+    }
+    Ok(first..=last)
+}
+
+/// Parses a positive number of seconds.
+fn parse_seconds(key: &str, value: &str) -> Result<Duration, Error> {
+    match value.parse::<u64>() {
+        Ok(seconds) if seconds > 0 => Ok(Duration::from_secs(seconds)),
+        _ => Err(Error::InvalidInput(format!(
+            "invalid {} '{}', expected a number of seconds",
+            key, value
+        ))),
+    }
+}
+
+/// How long each step of a download may take. Waiting in the queue of a bot has no
+/// limit, the connection is kept alive with PINGs meanwhile.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timeouts {
+    /// Connecting to the IRC server, TLS handshake included, or to a bot for DCC.
+    /// Bots making a passive offer have as long to connect to us.
+    pub connect: Duration,
+    /// From the connection until the server welcomed us and we are identified
+    pub registration: Duration,
+    /// From the JOIN until the server confirms it
+    pub join: Duration,
+    /// From the request of a pack until the bot offers it or queues us
+    pub offer: Duration,
+    /// How long a DCC transfer may go without receiving any data
+    pub stall: Duration,
+    /// How long the server may stay quiet before we PING it. The connection is
+    /// considered lost when the PING gets no answer within the same time.
+    pub ping_interval: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Timeouts {
+        Timeouts {
+            connect: Duration::from_secs(30),
+            registration: Duration::from_secs(60),
+            join: Duration::from_secs(60),
+            offer: Duration::from_secs(60),
+            stall: Duration::from_secs(60),
+            ping_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+/// How to pick another nickname when the server says ours is already in use.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NickStrategy {
+    /// `nick_`, then `nick__`...
+    Underscore,
+    /// `nick1`, then `nick2`...
+    Number,
+    /// `nick` followed by 4 random digits
+    Random,
+    /// Give up right away
+    Fail,
+}
+
+impl NickStrategy {
+    pub fn parse(name: &str) -> Result<NickStrategy, Error> {
+        match name {
+            "underscore" => Ok(NickStrategy::Underscore),
+            "number" => Ok(NickStrategy::Number),
+            "random" => Ok(NickStrategy::Random),
+            "fail" => Ok(NickStrategy::Fail),
+            _ => Err(Error::InvalidInput(format!(
+                "invalid nick strategy '{}', expected underscore, number, random or fail",
+                name
+            ))),
+        }
+    }
+
+    /// The nickname to try after `attempt` refusals of `nickname`, starting at 1.
+    pub fn alternative(&self, nickname: &str, attempt: u32) -> Option<String> {
+        match self {
+            NickStrategy::Underscore => Some(format!("{}{}", nickname, "_".repeat(attempt as usize))),
+            NickStrategy::Number => Some(format!("{}{}", nickname, attempt)),
+            NickStrategy::Random => Some(format!("{}{:04}", nickname, random_number() % 10_000)),
+            NickStrategy::Fail => None,
+        }
+    }
+}
+
+/// Default config file: `$XDG_CONFIG_HOME/anime-cli/config`, falling back to
+/// `~/.config/anime-cli/config`.
+pub fn config_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("anime-cli").join("config"))
+}
+
+/// The default nickname followed by 4 random digits, so that users do not all share
+/// the same nickname.
+pub fn random_nickname() -> String {
+    format!("{}{:04}", DEFAULT_NICKNAME, random_number() % 10_000)
+}
+
+fn random_number() -> u64 {
+    // The standard library seeds every RandomState randomly
+    RandomState::new().build_hasher().finish()
+}
@@ -0,0 +1,189 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::time::Duration;
+
+use native_tls::{Certificate, TlsConnector, TlsStream};
+
+use crate::error::Error;
+
+const IRC_PORT: u16 = 6667;
+const IRCS_PORT: u16 = 6697;
+
+/// Where to connect, parsed from `host[:port]`, `irc://host[:port]` or `ircs://host[:port]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerAddress {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+}
+
+impl ServerAddress {
+    /// The `ircs://` scheme always enables TLS, `irc://` never does and a bare
+    /// `host[:port]` uses TLS when `tls` is set. The port defaults to 6697 with TLS
+    /// and to 6667 without.
+    pub fn parse(server: &str, tls: bool) -> Result<ServerAddress, Error> {
+        let (tls, address) = if let Some(address) = server.strip_prefix("ircs://") {
+            (true, address)
+        } else if let Some(address) = server.strip_prefix("irc://") {
+            (false, address)
+        } else {
+            (tls, server)
+        };
+        let address = address.trim_end_matches('/');
+        let invalid = || Error::InvalidInput(format!("Invalid IRC server address '{}'", server));
+
+        // IPv6 literals are written in brackets, e.g. [::1]:6697
+        let (host, port) = if let Some(rest) = address.strip_prefix('[') {
+            let end = rest.find(']').ok_or_else(invalid)?;
+            match &rest[end + 1..] {
+                "" => (&rest[..end], None),
+                port => (&rest[..end], Some(port.strip_prefix(':').ok_or_else(invalid)?)),
+            }
+        } else {
+            match address.rfind(':') {
+                Some(i) => (&address[..i], Some(&address[i + 1..])),
+                None => (address, None),
+            }
+        };
+        if host.is_empty() || host.contains(char::is_whitespace) {
+            return Err(invalid());
+        }
+        let port = match port {
+            Some(port) => port.parse::<u16>().map_err(|_| invalid())?,
+            None if tls => IRCS_PORT,
+            None => IRC_PORT,
+        };
+        Ok(ServerAddress {
+            host: host.to_string(),
+            port,
+            tls,
+        })
+    }
+}
+
+/// A connection to an IRC server, in clear text or over TLS.
+pub enum IrcStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl IrcStream {
+    /// Connects to the server. With TLS the certificate of the server is verified
+    /// against the system trust store, and against `ca_file` when given: a PEM
+    /// certificate of a custom authority, or the self-signed certificate of the server.
+    /// When `pinned`, `ca_file` is the only certificate trusted, the system ones are not.
+    ///
+    /// Connecting and the TLS handshake each give up after `timeout`.
+    pub fn connect(
+        address: &ServerAddress,
+        ca_file: Option<&Path>,
+        pinned: bool,
+        timeout: Duration,
+    ) -> Result<IrcStream, Error> {
+        let name = format!("{}:{}", address.host, address.port);
+        let tcp = connect_tcp(&address.host, address.port, timeout)
+            .map_err(Error::network(format!("Failed to connect to {}", name)))?;
+        if !address.tls {
+            return Ok(IrcStream::Plain(tcp));
+        }
+
+        if pinned && ca_file.is_none() {
+            return Err(Error::InvalidInput("pinning needs a certificate to trust, see tls_ca".to_string()));
+        }
+
+        let mut builder = TlsConnector::builder();
+        if let Some(path) = ca_file {
+            let pem = fs::read(path)
+                .map_err(Error::filesystem(format!("Could not read CA certificate {}", path.display())))?;
+            let certificate = Certificate::from_pem(&pem).map_err(|e| {
+                Error::InvalidInput(format!("Invalid CA certificate {}: {}", path.display(), e))
+            })?;
+            builder.add_root_certificate(certificate);
+        }
+        // Otherwise any certificate of a public authority for the host would do
+        builder.disable_built_in_roots(pinned);
+        let connector = builder
+            .build()
+            .map_err(Error::network("Failed to set up TLS"))?;
+
+        // Do not wait forever on a server which does not speak TLS
+        tcp.set_read_timeout(Some(timeout))
+            .map_err(Error::network(format!("Failed to connect to {}", name)))?;
+        match connector.connect(&address.host, tcp) {
+            Ok(stream) => Ok(IrcStream::Tls(Box::new(stream))),
+            Err(e) => Err(Error::Network(
+                format!("TLS handshake with {} failed", name),
+                Some(e.to_string().into()),
+            )),
+        }
+    }
+
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            IrcStream::Plain(stream) => stream,
+            IrcStream::Tls(stream) => stream.get_ref(),
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.tcp().set_read_timeout(timeout)
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.tcp().set_write_timeout(timeout)
+    }
+
+    /// Our end of the connection, the address bots can reach us at unless we are behind NAT.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.tcp().local_addr()
+    }
+
+    /// Closes the connection, saying goodbye properly to TLS servers.
+    pub fn shutdown(&mut self) {
+        if let IrcStream::Tls(stream) = self {
+            stream.shutdown().ok();
+        }
+        self.tcp().shutdown(Shutdown::Both).ok();
+    }
+}
+
+impl Read for IrcStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            IrcStream::Plain(stream) => stream.read(buf),
+            IrcStream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for IrcStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            IrcStream::Plain(stream) => stream.write(buf),
+            IrcStream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            IrcStream::Plain(stream) => stream.flush(),
+            IrcStream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Connects to the first address of `host` which answers within `timeout`.
+pub fn connect_tcp(host: &str, port: u16, timeout: Duration) -> std::io::Result<TcpStream> {
+    let mut last_error = None;
+    for address in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::NotFound, format!("no address found for {}", host))
+    }))
+}
@@ -0,0 +1,137 @@
+use std::fmt;
+
+use crate::anime_episodes::EpisodeParseError;
+
+/// Underlying error which caused a failure, kept for diagnostics.
+pub type Cause = Box<dyn std::error::Error + Send + Sync>;
+
+/// Everything that can go wrong while searching or downloading.
+/// Each kind maps to its own process exit code, see [`Error::exit_code`].
+/// New kinds of errors may be added.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Invalid command line option or user input
+    InvalidInput(String),
+    /// Nothing matches the query, the episode or the filters
+    NotFound(String),
+    /// Packages were found but the bot serving them is unknown or offline
+    BotUnavailable(String),
+    /// The network or the remote host could not be reached
+    Network(String, Option<Cause>),
+    /// The search API answered with an error or with unexpected data
+    Api(String, Option<Cause>),
+    /// The IRC server or the bot did not behave as expected
+    Irc(String, Option<Cause>),
+    /// A DCC file transfer failed
+    Dcc(String, Option<Cause>),
+    /// A file could not be read or written, e.g. because the disk is full
+    Filesystem(String, Option<Cause>),
+    /// A downloaded file does not match the CRC32 of its filename
+    Corrupted(String),
+    /// The user asked to stop with Ctrl-C
+    Interrupted,
+}
+
+impl Error {
+    /// Exit codes of the command line, also documented in the README:
+    ///
+    /// | Code | Error            |
+    /// |------|------------------|
+    /// | 2    | InvalidInput     |
+    /// | 3    | NotFound         |
+    /// | 4    | BotUnavailable   |
+    /// | 5    | Network          |
+    /// | 6    | Api              |
+    /// | 7    | Irc              |
+    /// | 8    | Dcc              |
+    /// | 9    | Filesystem       |
+    /// | 10   | Corrupted        |
+    /// | 130  | Interrupted      |
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::InvalidInput(_) => 2,
+            Error::NotFound(_) => 3,
+            Error::BotUnavailable(_) => 4,
+            Error::Network(_, _) => 5,
+            Error::Api(_, _) => 6,
+            Error::Irc(_, _) => 7,
+            Error::Dcc(_, _) => 8,
+            Error::Filesystem(_, _) => 9,
+            Error::Corrupted(_) => 10,
+            // Standard exit code for SIGINT
+            Error::Interrupted => 130,
+        }
+    }
+
+    /// Builds a `map_err` adapter, e.g. `.map_err(Error::network("Failed to send PONG"))`.
+    pub fn network<E: Into<Cause>>(message: impl Into<String>) -> impl FnOnce(E) -> Error {
+        let message = message.into();
+        move |e| Error::Network(message, Some(e.into()))
+    }
+
+    /// Same as [`Error::network`] for DCC transfer errors.
+    pub fn dcc<E: Into<Cause>>(message: impl Into<String>) -> impl FnOnce(E) -> Error {
+        let message = message.into();
+        move |e| Error::Dcc(message, Some(e.into()))
+    }
+
+    /// Same as [`Error::network`] for filesystem errors.
+    pub fn filesystem<E: Into<Cause>>(message: impl Into<String>) -> impl FnOnce(E) -> Error {
+        let message = message.into();
+        move |e| Error::Filesystem(message, Some(e.into()))
+    }
+
+    /// Prepends some context to the message, keeping the kind of error.
+    pub fn prefixed<T: fmt::Display>(self, prefix: T) -> Error {
+        let prefix = |message: String| format!("{}: {}", prefix, message);
+        match self {
+            Error::InvalidInput(m) => Error::InvalidInput(prefix(m)),
+            Error::NotFound(m) => Error::NotFound(prefix(m)),
+            Error::BotUnavailable(m) => Error::BotUnavailable(prefix(m)),
+            Error::Network(m, c) => Error::Network(prefix(m), c),
+            Error::Api(m, c) => Error::Api(prefix(m), c),
+            Error::Irc(m, c) => Error::Irc(prefix(m), c),
+            Error::Dcc(m, c) => Error::Dcc(prefix(m), c),
+            Error::Filesystem(m, c) => Error::Filesystem(prefix(m), c),
+            Error::Corrupted(m) => Error::Corrupted(prefix(m)),
+            Error::Interrupted => Error::Interrupted,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidInput(m)
+            | Error::NotFound(m)
+            | Error::BotUnavailable(m)
+            | Error::Network(m, _)
+            | Error::Api(m, _)
+            | Error::Irc(m, _)
+            | Error::Dcc(m, _)
+            | Error::Filesystem(m, _)
+            | Error::Corrupted(m) => write!(f, "{}", m),
+            Error::Interrupted => write!(f, "Interrupted by user"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Network(_, Some(c))
+            | Error::Api(_, Some(c))
+            | Error::Irc(_, Some(c))
+            | Error::Dcc(_, Some(c))
+            | Error::Filesystem(_, Some(c)) => Some(c.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<EpisodeParseError> for Error {
+    fn from(e: EpisodeParseError) -> Error {
+        Error::InvalidInput(e.to_string())
+    }
+}
@@ -0,0 +1,51 @@
+/// What happens while connecting to IRC and downloading packs, in the order it
+/// usually happens. Sizes and positions are in bytes.
+/// New kinds of events may be added.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Event {
+    Connecting { server: String },
+    Connected { server: String },
+    /// The server refused our nickname, the alternative is tried instead
+    NicknameRefused { nickname: String, alternative: String },
+    /// Logged in to the account of the request, with SASL or NickServ
+    Authenticated { account: String },
+    Joined { channel: String },
+    PackRequested { bot: String, pack: i32 },
+    /// The bot has no free slot and put us in its queue
+    Queued { bot: String, position: Option<u32>, message: String },
+    /// The bot answered that it will not send a requested pack
+    Refused { bot: String, reason: String },
+    DccOffer { filename: String, size: u64 },
+    /// A DCC offer was ignored because it does not come from the bot or was not requested
+    OfferRejected { sender: String, offer: String, reason: String },
+    /// A partial file exists, the bot is asked to send the rest only
+    ResumeRequested { filename: String, position: u64 },
+    ResumeAccepted { filename: String, position: u64 },
+    /// The file is already fully downloaded, the offer is ignored
+    AlreadyComplete { filename: String },
+    TransferStarted { filename: String, size: u64, position: u64 },
+    Progress { filename: String, position: u64, size: u64 },
+    /// The file matches the CRC32 of its filename, sent before `Completed`
+    Verified { filename: String, crc: u32 },
+    Completed { filename: String },
+    Failed { filename: String, error: String },
+    /// The transfer was stopped because the user asked to stop
+    Cancelled { filename: String },
+    /// Something unexpected happened but the downloads go on
+    Warning { message: String },
+}
+
+/// Receives the events of a download. Listeners are called from the IRC loop as
+/// well as from the download threads, so they have to be thread safe.
+///
+/// Any `Fn(&Event)` closure is a listener, e.g. `|_: &Event| ()` to ignore them all.
+pub trait EventListener: Send + Sync {
+    fn on_event(&self, event: &Event);
+}
+
+impl<F: Fn(&Event) + Send + Sync> EventListener for F {
+    fn on_event(&self, event: &Event) {
+        self(event)
+    }
+}
@@ -0,0 +1,200 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+
+/// A message received from an IRC server, e.g.
+/// `@time=2020-01-01T00:00:00Z :Bot!bot@host PRIVMSG someone :hello there`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    /// IRCv3 tags with their unescaped values, empty when a tag has no value
+    pub tags: Vec<(String, String)>,
+    pub prefix: Option<Prefix>,
+    /// Upper case command or three digit numeric reply
+    pub command: String,
+    /// Parameters before the trailing one
+    pub params: Vec<String>,
+    /// Last parameter, the one introduced with a colon which may contain spaces
+    pub trailing: Option<String>,
+}
+
+/// Sender of a message: a server name or `nick!user@host`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prefix {
+    /// Nickname of a user, or name of a server
+    pub nick: String,
+    pub user: Option<String>,
+    pub host: Option<String>,
+}
+
+impl Message {
+    /// Parses one line, with or without its line ending. Returns `None` for lines
+    /// without a command.
+    pub fn parse(line: &str) -> Option<Message> {
+        let mut rest = line.trim_end_matches(&['\r', '\n'][..]);
+
+        let mut tags = Vec::new();
+        if let Some(tagged) = rest.strip_prefix('@') {
+            let (raw_tags, remaining) = split_word(tagged);
+            tags = raw_tags
+                .split(';')
+                .filter(|tag| !tag.is_empty())
+                .map(|tag| match tag.find('=') {
+                    Some(i) => (tag[..i].to_string(), unescape_tag_value(&tag[i + 1..])),
+                    None => (tag.to_string(), String::new()),
+                })
+                .collect();
+            rest = remaining;
+        }
+
+        let mut prefix = None;
+        if let Some(prefixed) = rest.strip_prefix(':') {
+            let (raw_prefix, remaining) = split_word(prefixed);
+            prefix = Some(Prefix::parse(raw_prefix));
+            rest = remaining;
+        }
+
+        let (command, mut rest) = split_word(rest);
+        if command.is_empty() {
+            return None;
+        }
+
+        let mut params = Vec::new();
+        let mut trailing = None;
+        while !rest.is_empty() {
+            if let Some(last) = rest.strip_prefix(':') {
+                trailing = Some(last.to_string());
+                break;
+            }
+            let (param, remaining) = split_word(rest);
+            params.push(param.to_string());
+            rest = remaining;
+        }
+
+        Some(Message {
+            tags,
+            prefix,
+            command: command.to_uppercase(),
+            params,
+            trailing,
+        })
+    }
+
+    /// Nickname of the sender, if any.
+    pub fn nick(&self) -> Option<&str> {
+        self.prefix.as_ref().map(|prefix| prefix.nick.as_str())
+    }
+
+    /// Whether the message was sent by `nick`, nicknames being case insensitive.
+    pub fn is_from(&self, nick: &str) -> bool {
+        self.nick().is_some_and(|sender| sender.eq_ignore_ascii_case(nick))
+    }
+
+    /// The `n`th parameter, counting the trailing one.
+    pub fn arg(&self, n: usize) -> Option<&str> {
+        match self.params.get(n) {
+            Some(param) => Some(param),
+            None if n == self.params.len() => self.trailing.as_deref(),
+            None => None,
+        }
+    }
+
+    /// The last parameter, usually the text of the message.
+    pub fn last_arg(&self) -> Option<&str> {
+        self.trailing.as_deref().or_else(|| self.params.last().map(String::as_str))
+    }
+
+    /// The body of a CTCP message such as `\x01DCC SEND file 1 2 3\x01`, sent as
+    /// PRIVMSG or NOTICE.
+    pub fn ctcp(&self) -> Option<&str> {
+        if self.command != "PRIVMSG" && self.command != "NOTICE" {
+            return None;
+        }
+        let text = self.last_arg()?.strip_prefix('\x01')?;
+        // The closing delimiter is optional
+        Some(text.strip_suffix('\x01').unwrap_or(text))
+    }
+}
+
+/// Address of a DCC peer, as found in DCC SEND offers: an IPv4 address as a 32-bit
+/// integer, as is traditional, an IPv6 or IPv4 literal, or a hostname.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DccAddress {
+    Ip(IpAddr),
+    Host(String),
+}
+
+impl DccAddress {
+    pub fn parse(text: &str) -> Option<DccAddress> {
+        if !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit()) {
+            return text.parse::<u32>().ok().map(|ip| DccAddress::Ip(IpAddr::V4(Ipv4Addr::from(ip))));
+        }
+        if let Ok(ip) = text.trim_start_matches('[').trim_end_matches(']').parse() {
+            return Some(DccAddress::Ip(ip));
+        }
+        let is_hostname = text.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        });
+        if is_hostname {
+            Some(DccAddress::Host(text.to_string()))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for DccAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DccAddress::Ip(ip) => write!(f, "{}", ip),
+            DccAddress::Host(host) => write!(f, "{}", host),
+        }
+    }
+}
+
+impl Prefix {
+    fn parse(prefix: &str) -> Prefix {
+        let (nick_user, host) = match prefix.find('@') {
+            Some(i) => (&prefix[..i], Some(prefix[i + 1..].to_string())),
+            None => (prefix, None),
+        };
+        let (nick, user) = match nick_user.find('!') {
+            Some(i) => (&nick_user[..i], Some(nick_user[i + 1..].to_string())),
+            None => (nick_user, None),
+        };
+        Prefix {
+            nick: nick.to_string(),
+            user,
+            host,
+        }
+    }
+}
+
+/// Splits at the first space, skipping the spaces before the remainder.
+fn split_word(text: &str) -> (&str, &str) {
+    match text.find(' ') {
+        Some(i) => (&text[..i], text[i..].trim_start_matches(' ')),
+        None => (text, ""),
+    }
+}
+
+fn unescape_tag_value(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(':') => unescaped.push(';'),
+            Some('s') => unescaped.push(' '),
+            Some('r') => unescaped.push('\r'),
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            // A trailing backslash is dropped
+            None => {}
+        }
+    }
+    unescaped
+}
@@ -0,0 +1,54 @@
+//! Find anime on XDCC pack indexes and download it from IRC bots.
+//!
+//! Searching goes through a [`SearchProvider`], such as [`Nibl`] or a [`LocalIndex`],
+//! and yields [`DCCPackage`]s. Packages are then grouped by bot into an
+//! [`IRCRequest`] and downloaded with [`connect_and_download`], which reports its
+//! progress to an [`EventListener`].
+//!
+//! ```no_run
+//! use std::path::PathBuf;
+//! use std::sync::atomic::AtomicBool;
+//! use std::sync::Arc;
+//!
+//! use anime_cli::anime_filter::Preferences;
+//! use anime_cli::anime_verify::MismatchAction;
+//! use anime_cli::{connect_and_download, find_package, Event, IRCRequest, Nibl};
+//!
+//! # fn main() -> Result<(), anime_cli::Error> {
+//! let query = "steins gate 0".to_string();
+//! let package = find_package(&Nibl::new(), &query, &Some(1), &Preferences::default())?;
+//! let mut request = IRCRequest::new("irc.rizon.net:6697", "nibl", "someRustacean", &package.bot, vec![package.number]);
+//! request.filenames = vec![package.filename];
+//! request.tls = true;
+//! request.output_dir = PathBuf::from("Downloads");
+//! request.subfolder = Some("{series}".to_string());
+//! request.on_mismatch = MismatchAction::Rename;
+//! let listener = Arc::new(|event: &Event| println!("{:?}", event));
+//! connect_and_download(request, Arc::new(AtomicBool::new(false)), listener)?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod anime_dl;
+pub mod anime_episodes;
+pub mod anime_filter;
+pub mod anime_find;
+pub mod anime_index;
+pub mod anime_output;
+pub mod anime_verify;
+mod auth;
+pub mod config;
+pub mod connection;
+pub mod error;
+pub mod events;
+pub mod irc;
+pub mod xdcc;
+
+pub use anime_dl::{connect_and_download, list_packs, IRCRequest};
+pub use auth::Credentials;
+pub use anime_find::{
+    find_all_packages, find_package, find_packages, find_packages_by_episode, DCCPackage,
+    LocalIndex, Nibl, SearchProvider,
+};
+pub use error::Error;
+pub use events::{Event, EventListener};
@@ -1,20 +1,184 @@
-mod anime_dl;
-mod anime_find;
+mod anime_pick;
+mod progress;
 
-use getopts::Options;
+use anime_cli::anime_filter::Preferences;
+use anime_cli::anime_find::SearchProvider;
+use anime_cli::config::Config;
+use anime_cli::error::Error;
+use anime_cli::anime_verify::{self, Verification};
+use anime_cli::{anime_dl, anime_episodes, anime_find, anime_index};
+use getopts::{Matches, Options};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 
-static IRC_SERVER: &str = "irc.rizon.net:6667";
-static IRC_CHANNEL: &str = "nibl";
-static IRC_NICKNAME: &str = "randomRustacean";
-
 fn print_usage(program: &str, opts: Options) {
     let msg = opts.short_usage(program);
     print!("{}", opts.usage(&msg));
 }
 
+/// Reports the error, along with its causes, and exits with its exit code.
+fn fail(error: Error) -> ! {
+    match error {
+        Error::Interrupted => eprintln!("{}", error),
+        _ => eprintln!("Error: {}", error),
+    }
+    let mut source = std::error::Error::source(&error);
+    while let Some(cause) = source {
+        eprintln!("  Caused by: {}", cause);
+        source = cause.source();
+    }
+    exit(error.exit_code());
+}
+
+fn parse_args(program: &str, opts: &Options, args: &[String]) -> Matches {
+    match opts.parse(args) {
+        Ok(m) => m,
+        Err(error) => {
+            eprintln!("{}.", error);
+            eprintln!("{}", opts.short_usage(program));
+            exit(Error::InvalidInput(error.to_string()).exit_code());
+        }
+    }
+}
+
+fn add_search_options(opts: &mut Options) {
+    opts.optopt(
+        "p",
+        "provider",
+        "Where to search packages: nibl (default), index or file:PATH",
+        "PROVIDER",
+    )
+    .optopt(
+        "r",
+        "resolution",
+        "Only keep packages with this resolution, e.g. 1080p",
+        "RESOLUTION",
+    )
+    .optopt("g", "group", "Only keep packages from this release group", "GROUP")
+    .optmulti(
+        "x",
+        "exclude",
+        "Drop packages whose filename contains this text (repeatable)",
+        "TEXT",
+    )
+    .optopt(
+        "j",
+        "jobs",
+        "Number of episodes searched at the same time when they are not all found at once",
+        "NUMBER",
+    )
+    .optflag("", "refresh-cache", "Fetch the NIBL bot list again instead of using the cached one");
+}
+
+fn add_connection_options(opts: &mut Options) {
+    opts.optopt(
+        "s",
+        "server",
+        "IRC server: host[:port], irc://host[:port] or ircs://host[:port]",
+        "SERVER",
+    )
+    .optopt("", "channel", "IRC channel to join", "CHANNEL")
+    .optopt("n", "nickname", "IRC nickname, random by default", "NICK")
+    .optopt(
+        "",
+        "nick-strategy",
+        "Alternative nicknames to try when taken: underscore (default), number, random or fail",
+        "STRATEGY",
+    )
+    .optflag("", "tls", "Connect to IRC over TLS, like an ircs:// server")
+    .optopt(
+        "",
+        "tls-ca",
+        "PEM certificate of an authority, or the self-signed one of the server, to trust for TLS",
+        "FILE",
+    )
+    .optflag("", "tls-pin", "Trust only the --tls-ca certificate, not the system ones")
+    .optopt(
+        "",
+        "dcc-ports",
+        "Local ports to listen on for passive DCC offers, e.g. 49152-49159",
+        "RANGE",
+    )
+    .optopt("", "external-ip", "Public address given to bots for passive DCC, when behind NAT", "IP")
+    .optopt("", "config", "Config file to use instead of the default one", "FILE");
+}
+
+/// Loads the config file and the environment, then applies the connection options.
+fn load_config(matches: &Matches) -> Config {
+    let path = matches.opt_str("config").map(PathBuf::from);
+    let mut config = Config::load(path.as_deref()).unwrap_or_else(|e| fail(e));
+    let options = [
+        ("server", "server"),
+        ("channel", "channel"),
+        ("nickname", "nickname"),
+        ("nick-strategy", "nick_strategy"),
+        ("tls-ca", "tls_ca"),
+        ("dcc-ports", "dcc_ports"),
+        ("external-ip", "external_ip"),
+    ];
+    for (option, key) in options.iter() {
+        if let Some(value) = matches.opt_str(option) {
+            config
+                .set(key, &value)
+                .unwrap_or_else(|e| fail(e.prefixed(format!("--{}", option))));
+        }
+    }
+    if matches.opt_present("tls") {
+        config.tls = true;
+    }
+    if matches.opt_present("tls-pin") {
+        config.tls_pin = true;
+    }
+    config
+}
+
+fn irc_request(config: &Config, bot: &str, packages: Vec<i32>) -> anime_dl::IRCRequest {
+    let mut request = anime_dl::IRCRequest::new(&config.server, &config.channel, &config.nickname, bot, packages);
+    request.nick_strategy = config.nick_strategy;
+    request.nick_attempts = config.nick_attempts;
+    request.tls = config.tls;
+    request.tls_ca = config.tls_ca.clone();
+    request.tls_pin = config.tls_pin;
+    request.credentials = config.credentials();
+    request.output_dir = config.output_dir.clone().unwrap_or_default();
+    request.subfolder = config.subfolder.clone();
+    request.on_mismatch = config.on_mismatch;
+    request.dcc_ports = config.dcc_ports.clone();
+    request.external_ip = config.external_ip;
+    request.timeouts = config.timeouts;
+    request
+}
+
+fn parse_preferences(matches: &Matches) -> Preferences {
+    Preferences {
+        resolution: matches.opt_str("r"),
+        group: matches.opt_str("g"),
+        exclude: matches.opt_strs("x"),
+    }
+}
+
+fn parse_jobs(matches: &Matches) -> usize {
+    match matches.opt_str("j").map(|jobs| jobs.parse::<usize>()) {
+        None => 1,
+        Some(Ok(jobs)) if jobs > 0 => jobs,
+        Some(_) => fail(Error::InvalidInput(
+            "The number of jobs must be a positive integer.".to_string(),
+        )),
+    }
+}
+
+fn parse_provider(matches: &Matches) -> Box<dyn SearchProvider> {
+    if matches.opt_present("refresh-cache") {
+        anime_find::refresh_bot_list().unwrap_or_else(|e| fail(e));
+    }
+
+    let name = matches.opt_str("p").unwrap_or_else(|| "nibl".to_string());
+    anime_find::provider_from_name(&name).unwrap_or_else(|e| fail(e))
+}
+
 fn main() {
     // Set up graceful shutdown handler
     let shutdown = Arc::new(AtomicBool::new(false));
@@ -26,15 +190,42 @@ fn main() {
 
     let args: Vec<String> = std::env::args().collect();
     let program = args[0].clone();
+    match args.get(1).map(String::as_str) {
+        Some("search") => search(&format!("{} search", program), &args[2..]),
+        Some("index") => index(&format!("{} index", program), &args[2..], shutdown),
+        Some("verify") => verify(&format!("{} verify", program), &args[2..]),
+        _ => {}
+    }
+
     let mut opts = Options::new();
     opts.reqopt("q", "query", "Query to run", "QUERY")
         .optopt(
             "e",
             "episodes",
-            "Episode number(s), separated with comma",
-            "NUMBER",
+            "Episode number(s) or ranges, e.g. 1,3,7-9 or 5-, latest or all",
+            "EPISODES",
+        )
+        .optflag(
+            "i",
+            "interactive",
+            "Choose among all matching packages instead of taking the first one",
+        )
+        .optopt("d", "output-dir", "Directory to save the files in, the current one by default", "DIR")
+        .optopt(
+            "",
+            "subfolder",
+            "Subfolder of the output directory for each file, e.g. {series} or {group}/{series}",
+            "TEMPLATE",
+        )
+        .optopt(
+            "",
+            "on-mismatch",
+            "What to do with files not matching the CRC32 of their name: keep (default), delete or rename",
+            "ACTION",
         )
         .optflag("h", "help", "print this help menu");
+    add_search_options(&mut opts);
+    add_connection_options(&mut opts);
 
     // Unfortunately, cannot use getopts to check for a single optional flag
     // https://github.com/rust-lang-nursery/getopts/issues/46
@@ -43,78 +234,276 @@ fn main() {
         exit(0);
     }
 
-    let matches = match opts.parse(&args[1..]) {
-        Ok(m) => m,
-        Err(error) => {
-            eprintln!("{}.", error);
-            eprintln!("{}", opts.short_usage(&program));
-            exit(1);
+    let matches = parse_args(&program, &opts, &args[1..]);
+    let mut config = load_config(&matches);
+    let options = [
+        ("output-dir", "output_dir"),
+        ("subfolder", "subfolder"),
+        ("on-mismatch", "on_mismatch"),
+    ];
+    for (option, key) in options.iter() {
+        if let Some(value) = matches.opt_str(option) {
+            config
+                .set(key, &value)
+                .unwrap_or_else(|e| fail(e.prefixed(format!("--{}", option))));
         }
-    };
+    }
 
     let query = matches.opt_str("q").unwrap();
-    let packages = match matches.opt_str("e") {
-        Some(ep) => match anime_find::find_packages(&query, &parse_episodes(ep)) {
-            Ok(pkgs) => pkgs,
-            Err(e) => {
-                eprintln!("Error: {}", e);
-                exit(1);
-            }
-        },
-        None => match anime_find::find_package(&query, &None) {
-            Ok(pkg) => vec![pkg],
-            Err(e) => {
-                eprintln!("Error: {}", e);
-                exit(1);
-            }
-        },
+    let provider = parse_provider(&matches);
+    let preferences = parse_preferences(&matches);
+    let jobs = parse_jobs(&matches);
+    let episodes = matches
+        .opt_str("e")
+        .map(|ep| parse_episodes(provider.as_ref(), &query, &ep, &preferences));
+    // Fall back to the first results when there is nobody to answer the prompt
+    let interactive = matches.opt_present("i") && std::io::stdin().is_terminal();
+
+    let found = if interactive {
+        pick_packages(provider.as_ref(), &query, &episodes, &preferences, jobs)
+    } else {
+        match episodes {
+            Some(ep) => anime_find::find_packages(provider.as_ref(), &query, &ep, &preferences, jobs),
+            None => anime_find::find_package(provider.as_ref(), &query, &None, &preferences)
+                .map(|pkg| vec![pkg]),
+        }
     };
+    let packages = found.unwrap_or_else(|e| fail(e));
 
     let mut packages_by_bot = std::collections::HashMap::new();
     for package in packages.iter() {
         packages_by_bot
             .entry(&package.bot)
             .or_insert(vec![])
-            .push(package.number);
+            .push(package);
     }
 
+    let listener = Arc::new(progress::ProgressListener::new());
     for (bot, packages) in packages_by_bot {
         // Check if shutdown was requested before starting new bot connection
         if shutdown.load(Ordering::SeqCst) {
             eprintln!("\nShutdown requested, exiting gracefully...");
-            exit(130); // Standard exit code for SIGINT
+            exit(Error::Interrupted.exit_code());
         }
 
-        let irc_request = anime_dl::IRCRequest {
-            server: IRC_SERVER.to_string(),
-            channel: IRC_CHANNEL.to_string(),
-            nickname: IRC_NICKNAME.to_string(),
-            bot: bot.to_owned(),
-            packages,
-        };
-        match anime_dl::connect_and_download(irc_request, shutdown.clone(), |_| ()) {
-            Ok(_) => {},
-            Err(e) => {
-                // Use appropriate exit code for interruption
-                let exit_code = if e == "Interrupted by user" { 130 } else { 1 };
-                eprintln!("{}", e);
-                exit(exit_code);
+        let mut irc_request = irc_request(&config, bot, packages.iter().map(|package| package.number).collect());
+        irc_request.filenames = packages.iter().map(|package| package.filename.clone()).collect();
+        if let Err(e) = anime_dl::connect_and_download(irc_request, shutdown.clone(), listener.clone()) {
+            fail(e);
+        }
+    }
+    exit(0);
+}
+
+/// Prints every package matching the query without downloading anything.
+fn search(program: &str, args: &[String]) -> ! {
+    let mut opts = Options::new();
+    opts.reqopt("q", "query", "Query to run", "QUERY")
+        .optopt(
+            "e",
+            "episodes",
+            "Episode number(s) or ranges, e.g. 1,3,7-9 or 5-, latest or all",
+            "EPISODES",
+        )
+        .optflag("", "json", "print one JSON object per line")
+        .optflag("h", "help", "print this help menu");
+    add_search_options(&mut opts);
+
+    if args.contains(&"-h".to_string()) || args.contains(&"--help".to_string()) {
+        print_usage(program, opts);
+        exit(0);
+    }
+
+    let matches = parse_args(program, &opts, args);
+
+    let query = matches.opt_str("q").unwrap();
+    let provider = parse_provider(&matches);
+    let preferences = parse_preferences(&matches);
+    let found = match matches.opt_str("e") {
+        Some(ep) => {
+            let episodes = parse_episodes(provider.as_ref(), &query, &ep, &preferences);
+            let jobs = parse_jobs(&matches);
+            anime_find::find_packages_by_episode(provider.as_ref(), &query, &episodes, &preferences, jobs)
+                .map(|by_episode| by_episode.into_iter().flat_map(|(_, pkgs)| pkgs).collect())
+        }
+        None => anime_find::find_all_packages(provider.as_ref(), &query, &None, &preferences),
+    };
+    let packages = found.unwrap_or_else(|e| fail(e));
+
+    if matches.opt_present("json") {
+        for package in packages.iter() {
+            match serde_json::to_string(package) {
+                Ok(line) => println!("{}", line),
+                Err(e) => fail(Error::Api(
+                    "Could not serialize package".to_string(),
+                    Some(Box::new(e)),
+                )),
             }
-        };
+        }
+    } else {
+        print_table(&packages);
     }
     exit(0);
 }
 
-fn parse_episodes(episodes: String) -> Vec<u16> {
-    episodes.split(",").map(parse_episode).collect::<Vec<_>>()
+/// Builds the local pack index from the pack lists of the given bots.
+fn index(program: &str, args: &[String], shutdown: Arc<AtomicBool>) -> ! {
+    let mut opts = Options::new();
+    opts.optmulti("b", "bot", "Bot to ask for its pack list (repeatable)", "BOT")
+        .optopt("o", "output", "Index file to update", "FILE")
+        .optflag("h", "help", "print this help menu");
+    add_connection_options(&mut opts);
+
+    if args.contains(&"-h".to_string()) || args.contains(&"--help".to_string()) {
+        print_usage(program, opts);
+        exit(0);
+    }
+
+    let matches = parse_args(program, &opts, args);
+    let config = load_config(&matches);
+
+    let bots = matches.opt_strs("b");
+    if bots.is_empty() {
+        eprintln!("Required option 'bot' missing.");
+        eprintln!("{}", opts.short_usage(program));
+        exit(Error::InvalidInput("Required option 'bot' missing".to_string()).exit_code());
+    }
+    let index_path = matches
+        .opt_str("o")
+        .map(PathBuf::from)
+        .unwrap_or_else(anime_index::index_path);
+
+    for bot in bots {
+        let irc_request = irc_request(&config, &bot, vec![]);
+        let pack_list = anime_dl::list_packs(&irc_request, shutdown.clone()).unwrap_or_else(|e| fail(e));
+        let packages = anime_index::parse_pack_list(&bot, &pack_list);
+        println!("{}: {} package(s)", bot, packages.len());
+        anime_index::update_index(&index_path, &bot, packages).unwrap_or_else(|e| fail(e));
+    }
+    exit(0);
 }
 
-fn parse_episode(episode: &str) -> u16 {
-    match episode.parse::<u16>() {
-        Ok(e) => e,
-        Err(_) => {
-            eprintln!("Error: '{}' is not a valid episode number. Episode numbers must be positive integers.", episode);
-            exit(1);
+/// Checks files against the CRC32 of their filename.
+fn verify(program: &str, args: &[String]) -> ! {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+
+    if args.contains(&"-h".to_string()) || args.contains(&"--help".to_string()) {
+        print_usage(&format!("{} FILE...", program), opts);
+        exit(0);
+    }
+
+    let matches = parse_args(program, &opts, args);
+    if matches.free.is_empty() {
+        eprintln!("No file to verify.");
+        eprintln!("{} FILE...", opts.short_usage(program));
+        exit(Error::InvalidInput("No file to verify".to_string()).exit_code());
+    }
+
+    // Every file is checked, the exit code is the one of the first failure
+    let mut failure = None;
+    for file in matches.free.iter() {
+        match anime_verify::verify_file(Path::new(file)) {
+            Ok(Verification::Valid { crc }) => println!("OK        {:08X}  {}", crc, file),
+            Ok(Verification::Mismatch { expected, actual }) => {
+                println!("CORRUPTED {:08X}  {} (expected {:08X})", actual, file, expected);
+                failure.get_or_insert(Error::Corrupted(format!("{} is corrupted", file)));
+            }
+            Ok(Verification::Unknown) => println!("NO CRC              {}", file),
+            Err(e) => {
+                println!("ERROR               {} ({})", file, e);
+                failure.get_or_insert(e);
+            }
+        }
+    }
+    match failure {
+        Some(e) => exit(e.exit_code()),
+        None => exit(0),
+    }
+}
+
+fn print_table(packages: &[anime_find::DCCPackage]) {
+    let header = ["BOT", "PACK", "EPISODE", "SIZE", "FILENAME"];
+    let rows = packages
+        .iter()
+        .map(|p| {
+            [
+                p.bot.clone(),
+                format!("#{}", p.number),
+                p.episode.map(|e| e.to_string()).unwrap_or_default(),
+                p.size.clone(),
+                p.filename.clone(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let mut widths = header.map(str::len);
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
         }
     }
+
+    let print_row = |cells: &[&str]| {
+        let line = cells
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, &width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    };
+    print_row(&header);
+    for row in rows.iter() {
+        print_row(&row.iter().map(String::as_str).collect::<Vec<_>>());
+    }
+}
+
+fn pick_packages(
+    provider: &dyn SearchProvider,
+    query: &String,
+    episodes: &Option<Vec<u16>>,
+    preferences: &Preferences,
+    jobs: usize,
+) -> Result<Vec<anime_find::DCCPackage>, Error> {
+    let episodes = match episodes {
+        Some(ep) => ep,
+        None => {
+            let found = anime_find::find_all_packages(provider, query, &None, preferences)?;
+            return anime_pick::pick_packages(found);
+        }
+    };
+    let mut packages = Vec::new();
+    for (ep, found) in anime_find::find_packages_by_episode(provider, query, episodes, preferences, jobs)? {
+        println!("Episode {}:", ep);
+        let picked = anime_pick::pick_packages(found).map_err(|e| e.prefixed(format!("Episode {}", ep)))?;
+        packages.extend(picked);
+    }
+    Ok(packages)
+}
+
+/// Parses the episode specification, looking up the available episodes when
+/// it is open-ended (`5-`, `latest` or `all`).
+fn parse_episodes(
+    provider: &dyn SearchProvider,
+    query: &String,
+    episodes: &str,
+    preferences: &Preferences,
+) -> Vec<u16> {
+    let specs = anime_episodes::parse_episodes(episodes).unwrap_or_else(|e| fail(e.into()));
+
+    let available = if anime_episodes::is_open_ended(&specs) {
+        anime_find::available_episodes(provider, query, preferences).unwrap_or_else(|e| fail(e))
+    } else {
+        Vec::new()
+    };
+
+    let resolved = anime_episodes::resolve_episodes(&specs, &available);
+    if resolved.is_empty() {
+        fail(Error::NotFound(format!(
+            "No episode of '{}' matches '{}'.",
+            query, episodes
+        )));
+    }
+    resolved
 }
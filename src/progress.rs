@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anime_cli::events::{Event, EventListener};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+/// Renders the download events as a spinner, then as one progress bar per file.
+pub struct ProgressListener {
+    mp: MultiProgress,
+    spinner: ProgressBar,
+    bars: Mutex<HashMap<String, ProgressBar>>,
+}
+
+impl ProgressListener {
+    pub fn new() -> ProgressListener {
+        let mp = MultiProgress::new();
+        let spinner = mp.add(ProgressBar::new_spinner());
+        spinner.set_style(ProgressStyle::default_spinner().template("{spinner:.cyan} {msg}").unwrap());
+        spinner.enable_steady_tick(Duration::from_millis(100));
+        ProgressListener {
+            mp,
+            spinner,
+            bars: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn add_bar(&self, filename: &str, size: u64, position: u64) {
+        // Clear the spinner once we start downloading
+        self.spinner.finish_and_clear();
+
+        let pb = self.mp.add(ProgressBar::new(size));
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("{msg} [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({percent}%) {bytes_per_sec} ETA: {eta}")
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+        let action = if position > 0 { "Resuming" } else { "Downloading" };
+        pb.set_message(format!("{} {}", action, filename));
+        pb.set_position(position);
+        pb.enable_steady_tick(Duration::from_millis(500));
+        self.bars.lock().unwrap_or_else(|e| e.into_inner()).insert(filename.to_string(), pb);
+    }
+
+    fn with_bar<F: FnOnce(&ProgressBar)>(&self, filename: &str, f: F) {
+        if let Some(pb) = self.bars.lock().unwrap_or_else(|e| e.into_inner()).get(filename) {
+            f(pb);
+        }
+    }
+}
+
+impl EventListener for ProgressListener {
+    fn on_event(&self, event: &Event) {
+        match event {
+            Event::Connecting { server } => self.spinner.set_message(format!("Connecting to {}...", server)),
+            Event::Connected { .. } => self.spinner.set_message("Connected! Waiting for the server..."),
+            Event::NicknameRefused { nickname, alternative } => self
+                .spinner
+                .set_message(format!("Nickname {} is taken, trying {}...", nickname, alternative)),
+            Event::Authenticated { account } => self.spinner.set_message(format!("Identified as {}", account)),
+            Event::Joined { channel } => self.spinner.set_message(format!("Joined #{}", channel)),
+            Event::PackRequested { bot, pack } => {
+                self.spinner.set_message(format!("Requesting package #{} from {}...", pack, bot))
+            }
+            Event::Queued { bot, position, .. } => match position {
+                Some(p) => self.spinner.set_message(format!("Queued by {} at position {}...", bot, p)),
+                None => self.spinner.set_message(format!("Queued by {}...", bot)),
+            },
+            Event::Refused { bot, reason } => self.spinner.set_message(format!("{} refused: {}", bot, reason)),
+            Event::ResumeRequested { position, .. } => {
+                self.spinner.set_message(format!("Requesting resume from {} bytes...", position))
+            }
+            Event::AlreadyComplete { filename } => {
+                self.spinner.set_message(format!("File {} already complete, skipping", filename))
+            }
+            Event::TransferStarted { filename, size, position } => self.add_bar(filename, *size, *position),
+            Event::Progress { filename, position, .. } => self.with_bar(filename, |pb| pb.set_position(*position)),
+            Event::Completed { filename } => {
+                self.with_bar(filename, |pb| pb.finish_with_message(format!("✓ Downloaded {}", filename)))
+            }
+            Event::Cancelled { filename } => {
+                self.with_bar(filename, |pb| pb.abandon_with_message(format!("✗ Interrupted {}", filename)))
+            }
+            Event::Failed { filename, error } => {
+                self.with_bar(filename, |pb| pb.abandon_with_message(format!("✗ Failed {}", filename)));
+                self.mp.println(format!("Download error: {}", error)).ok();
+            }
+            Event::OfferRejected { sender, offer, reason } => {
+                self.mp.println(format!("Warning: Ignored DCC offer from {} ({}): {}", sender, reason, offer)).ok();
+            }
+            Event::Warning { message } => {
+                self.mp.println(format!("Warning: {}", message)).ok();
+            }
+            // DccOffer, ResumeAccepted, Verified and events added later are not shown
+            _ => {}
+        }
+    }
+}
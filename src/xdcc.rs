@@ -0,0 +1,64 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    // Mostly the wording of iroffer, which most XDCC bots run
+    static ref QUEUED_REGEX: Regex = Regex::new(
+        r#"(?i)added you to the \w+ queue|queued (?:you )?in position|queue position|you are in position|already have that item queued"#
+    )
+    .unwrap();
+    static ref POSITION_REGEX: Regex = Regex::new(r#"(?i)position:? (\d+)"#).unwrap();
+    static ref INVALID_PACK_REGEX: Regex =
+        Regex::new(r#"(?i)invalid pack|pack (?:number )?(?:does not|doesn't) exist|no such pack"#).unwrap();
+    static ref ALREADY_REQUESTED_REGEX: Regex =
+        Regex::new(r#"(?i)already requested|already (?:have|has) (?:a|an active) transfer|already receiving"#).unwrap();
+    static ref QUEUE_FULL_REGEX: Regex = Regex::new(r#"(?i)queue (?:of size \d+ )?is full"#).unwrap();
+    static ref DENIED_REGEX: Regex =
+        Regex::new(r#"(?i)xdcc send denied|denied, you must|not allowed to|only serves"#).unwrap();
+    /// Bold, color, reset, reverse, italic and underline codes
+    static ref FORMATTING_REGEX: Regex = Regex::new(r#"\x03(?:\d{1,2}(?:,\d{1,2})?)?|[\x02\x0f\x16\x1d\x1f]"#).unwrap();
+}
+
+/// Answer of a bot to an `xdcc send` request, sent as a NOTICE or PRIVMSG.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BotReply {
+    /// The request waits in a queue of the bot, at this position when known
+    Queued { position: Option<u32> },
+    /// The pack does not exist
+    InvalidPack,
+    /// A transfer of the pack, or any transfer when the bot only allows one, is
+    /// already going on
+    AlreadyRequested,
+    /// Nobody can queue anymore, until a slot frees up
+    QueueFull,
+    /// The bot does not serve us, e.g. because we are not in its channel
+    Denied,
+}
+
+impl BotReply {
+    /// Recognizes the usual answers, `None` for anything else such as the
+    /// announcement of a transfer.
+    pub fn parse(text: &str) -> Option<BotReply> {
+        let text = strip_formatting(text);
+        // Refusals for lack of a free slot often add the request to the queue
+        if QUEUED_REGEX.is_match(&text) {
+            let position = POSITION_REGEX.captures(&text).and_then(|c| c[1].parse().ok());
+            Some(BotReply::Queued { position })
+        } else if INVALID_PACK_REGEX.is_match(&text) {
+            Some(BotReply::InvalidPack)
+        } else if ALREADY_REQUESTED_REGEX.is_match(&text) {
+            Some(BotReply::AlreadyRequested)
+        } else if QUEUE_FULL_REGEX.is_match(&text) {
+            Some(BotReply::QueueFull)
+        } else if DENIED_REGEX.is_match(&text) {
+            Some(BotReply::Denied)
+        } else {
+            None
+        }
+    }
+}
+
+/// Removes the mIRC formatting codes bots like to decorate their messages with.
+pub fn strip_formatting(text: &str) -> String {
+    FORMATTING_REGEX.replace_all(text, "").to_string()
+}
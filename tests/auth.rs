@@ -0,0 +1,124 @@
+mod common;
+
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+
+use anime_cli::config::Config;
+use anime_cli::{list_packs, Credentials, Error, IRCRequest};
+
+const PACK_LIST: &str = "#1  3x [700M] [Group] Show - 01 [720p].mkv\n";
+// base64 of "tester\0tester\0secret"
+const SASL_PAYLOAD: &str = "dGVzdGVyAHRlc3RlcgBzZWNyZXQ=";
+
+/// A network whose services know the account `tester` with the password `secret`.
+/// With `sasl`, SASL PLAIN is offered and succeeds with the right password. Records
+/// the lines sent by the client.
+fn start_server(sasl: bool) -> (String, Arc<Mutex<Vec<String>>>) {
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let received = lines.clone();
+    let mut negotiating = false;
+    let address = common::start_irc_server(move |line| {
+        received.lock().unwrap().push(line.to_string());
+        match line {
+            "CAP LS 302" if sasl => {
+                negotiating = true;
+                vec![":irc.test CAP * LS :multi-prefix sasl=PLAIN,EXTERNAL".to_string()]
+            }
+            // Servers without capabilities do not know the command
+            "CAP LS 302" => vec![":irc.test 421 * CAP :Unknown command".to_string()],
+            "CAP REQ :sasl" => vec![":irc.test CAP * ACK :sasl".to_string()],
+            "AUTHENTICATE PLAIN" => vec!["AUTHENTICATE +".to_string()],
+            "AUTHENTICATE dGVzdGVyAHRlc3RlcgBzZWNyZXQ=" => vec![
+                ":irc.test 900 tester tester!tester@test tester :You are now logged in as tester".to_string(),
+                ":irc.test 903 tester :SASL authentication successful".to_string(),
+            ],
+            _ if line.starts_with("AUTHENTICATE ") => {
+                vec![":irc.test 904 tester :SASL authentication failed".to_string()]
+            }
+            "CAP END" => {
+                negotiating = false;
+                common::network("USER tester 0 * tester")
+            }
+            _ if line.starts_with("USER ") && negotiating => vec![],
+            "PRIVMSG NickServ :IDENTIFY tester secret" => {
+                vec![":NickServ!service@rizon.net NOTICE tester :Password accepted - you are now recognized.".to_string()]
+            }
+            _ if line.starts_with("PRIVMSG NickServ :IDENTIFY") => {
+                vec![":NickServ!service@rizon.net NOTICE tester :Password incorrect.".to_string()]
+            }
+            _ if line.contains("xdcc list") => {
+                let port = common::start_dcc_sender(PACK_LIST.as_bytes().to_vec());
+                vec![common::dcc_send("Bot", "packlist.txt", port, PACK_LIST.len())]
+            }
+            _ => common::network(line),
+        }
+    });
+    (address, lines)
+}
+
+fn request(server: String, password: &str) -> IRCRequest {
+    let mut request = common::request(server, "Bot", vec![]);
+    request.credentials = Some(Credentials {
+        account: "tester".to_string(),
+        password: password.to_string(),
+    });
+    request
+}
+
+fn position(lines: &[String], prefix: &str) -> usize {
+    lines
+        .iter()
+        .position(|line| line.starts_with(prefix))
+        .unwrap_or_else(|| panic!("'{}' was not sent in {:?}", prefix, lines))
+}
+
+#[test]
+fn identifies_with_sasl_before_joining() {
+    let (server, lines) = start_server(true);
+    let pack_list = list_packs(&request(server, "secret"), Arc::new(AtomicBool::new(false)));
+    assert_eq!(pack_list.unwrap(), PACK_LIST);
+
+    let lines = lines.lock().unwrap();
+    assert_eq!(lines[0], "CAP LS 302");
+    assert!(position(&lines, &format!("AUTHENTICATE {}", SASL_PAYLOAD)) < position(&lines, "CAP END"));
+    assert!(position(&lines, "CAP END") < position(&lines, "JOIN "));
+    assert!(!lines.iter().any(|line| line.contains("NickServ")));
+}
+
+#[test]
+fn falls_back_to_nickserv_without_sasl() {
+    let (server, lines) = start_server(false);
+    let pack_list = list_packs(&request(server, "secret"), Arc::new(AtomicBool::new(false)));
+    assert_eq!(pack_list.unwrap(), PACK_LIST);
+
+    let lines = lines.lock().unwrap();
+    assert!(position(&lines, "PRIVMSG NickServ :IDENTIFY") < position(&lines, "JOIN "));
+}
+
+#[test]
+fn falls_back_to_nickserv_when_sasl_fails_and_reports_its_refusal() {
+    let (server, lines) = start_server(true);
+    match list_packs(&request(server, "wrong"), Arc::new(AtomicBool::new(false))) {
+        Err(Error::Irc(message, _)) => assert!(message.contains("Password incorrect")),
+        other => panic!("expected an IRC error, got {:?}", other.map(|_| ())),
+    }
+
+    let lines = lines.lock().unwrap();
+    assert!(position(&lines, "AUTHENTICATE ") < position(&lines, "PRIVMSG NickServ :IDENTIFY"));
+    assert!(!lines.iter().any(|line| line.starts_with("JOIN ")));
+}
+
+#[test]
+fn reads_credentials_from_the_config() {
+    let mut config = Config::default();
+    config.apply_str("nickname = someRustacean").unwrap();
+    assert_eq!(config.credentials(), None);
+
+    config.apply_vars(vec![("ANIME_CLI_PASSWORD".to_string(), "secret".to_string())]).unwrap();
+    let credentials = config.credentials().unwrap();
+    assert_eq!(credentials.account, "someRustacean");
+    assert_eq!(credentials.password, "secret");
+
+    config.apply_str("account = someAccount").unwrap();
+    assert_eq!(config.credentials().unwrap().account, "someAccount");
+}
@@ -0,0 +1,155 @@
+//! A scripted IRC server and DCC sender standing in for a network and its bots.
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, Shutdown, TcpListener};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anime_cli::{Event, EventListener, IRCRequest};
+use native_tls::{Identity, TlsAcceptor};
+
+pub const NICKNAME: &str = "tester";
+
+/// A request to `server` as `tester`, with the default nickname strategy and no TLS,
+/// saving files in the temporary directory.
+pub fn request(server: String, bot: &str, packages: Vec<i32>) -> IRCRequest {
+    let mut request = IRCRequest::new(&server, "nibl", NICKNAME, bot, packages);
+    request.output_dir = std::env::temp_dir();
+    request
+}
+
+/// An empty directory of its own for a test to download into.
+pub fn output_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("anime-cli-test-{}-{}", std::process::id(), name));
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Starts a plain IRC server for a single client, see [`serve`]. Returns its address.
+pub fn start_irc_server<F>(reply: F) -> String
+where
+    F: FnMut(&str) -> Vec<String> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        serve(stream, reply);
+    });
+    address
+}
+
+/// Answers every line sent by the client with the lines returned by `reply`, until
+/// the client hangs up.
+pub fn serve<S, F>(stream: S, mut reply: F)
+where
+    S: Read + Write,
+    F: FnMut(&str) -> Vec<String>,
+{
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    while reader.read_line(&mut line).unwrap_or(0) > 0 {
+        for answer in reply(line.trim_end()) {
+            if reader.get_mut().write_all(format!("{}\r\n", answer).as_bytes()).is_err() {
+                return;
+            }
+        }
+        line.clear();
+    }
+}
+
+/// Replies of a well-behaved network: welcomes the client once registered and
+/// confirms its JOIN. Other lines are not answered.
+pub fn network(line: &str) -> Vec<String> {
+    if line.starts_with("USER ") {
+        vec![
+            format!(":irc.test 001 {} :Welcome", NICKNAME),
+            format!(":irc.test 376 {} :End of /MOTD command.", NICKNAME),
+        ]
+    } else if let Some(channel) = line.strip_prefix("JOIN ") {
+        vec![format!(":{}!{}@test JOIN :{}", NICKNAME, NICKNAME, channel)]
+    } else {
+        vec![]
+    }
+}
+
+/// Serves `content` to the first client connecting to the returned port, like a
+/// bot sending a file over DCC.
+pub fn start_dcc_sender(content: Vec<u8>) -> u16 {
+    start_dcc_sender_on("127.0.0.1", content)
+}
+
+/// Same as [`start_dcc_sender`], listening on `ip`.
+pub fn start_dcc_sender_on(ip: &str, content: Vec<u8>) -> u16 {
+    let listener = TcpListener::bind((ip, 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        let (mut client, _) = listener.accept().unwrap();
+        client.write_all(&content).unwrap();
+        // Then wait for the client to hang up, which it does early when the content
+        // is shorter than the offer
+        client.shutdown(Shutdown::Write).ok();
+        client.read_to_end(&mut Vec::new()).ok();
+    });
+    port
+}
+
+/// A DCC SEND offer from `bot` for a file served on the local `port`.
+pub fn dcc_send(bot: &str, filename: &str, port: u16, size: usize) -> String {
+    dcc_send_from(bot, filename, &u32::from(Ipv4Addr::LOCALHOST).to_string(), port, size)
+}
+
+/// A DCC SEND offer from `bot` for a file served at `address`, as written in the offer.
+pub fn dcc_send_from(bot: &str, filename: &str, address: &str, port: u16, size: usize) -> String {
+    format!(
+        ":{}!bot@test PRIVMSG {} :\x01DCC SEND {} {} {} {}\x01",
+        bot, NICKNAME, filename, address, port, size
+    )
+}
+
+/// The pack list sent by [`start_tls_server`].
+pub const PACK_LIST: &str = "#1  3x [700M] [Group] Show - 01 [720p].mkv\n";
+
+/// Path of a file of `tests/fixtures`.
+pub fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+/// Serves a single client over TLS as `localhost`, with a certificate of the
+/// `ca.pem` authority, sending the pack list over DCC when asked. Returns the port
+/// to connect to.
+pub fn start_tls_server() -> u16 {
+    let read = |name| std::fs::read(fixture(name)).unwrap();
+    let identity = Identity::from_pkcs8(&read("server.pem"), &read("server.key")).unwrap();
+    let acceptor = TlsAcceptor::new(identity).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    thread::spawn(move || {
+        let (tcp, _) = listener.accept().unwrap();
+        let stream = match acceptor.accept(tcp) {
+            Ok(stream) => stream,
+            // The client refused our certificate
+            Err(_) => return,
+        };
+        serve(stream, |line| {
+            if line.contains("xdcc list") {
+                let port = start_dcc_sender(PACK_LIST.as_bytes().to_vec());
+                vec![dcc_send("Bot", "packlist.txt", port, PACK_LIST.len())]
+            } else {
+                network(line)
+            }
+        });
+    });
+    port
+}
+
+/// A listener collecting the events of a download.
+pub fn recorder() -> (Arc<Mutex<Vec<Event>>>, Arc<dyn EventListener>) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    (events, Arc::new(move |event: &Event| recorded.lock().unwrap().push(event.clone())))
+}
@@ -0,0 +1,85 @@
+use std::path::PathBuf;
+
+use anime_cli::config::{random_nickname, Config};
+use anime_cli::Error;
+
+#[test]
+fn reads_config_files() {
+    let path = std::env::temp_dir().join(format!("anime-cli-test-{}-config", std::process::id()));
+    std::fs::write(
+        &path,
+        "# Connection settings\n\
+         server = ircs://irc.example.org\n\
+         \n\
+         channel = #anime\n\
+         nickname=someRustacean\n\
+         tls_ca = /etc/ssl/ca.pem\n",
+    )
+    .unwrap();
+    let config = Config::load(Some(&path));
+    std::fs::remove_file(&path).ok();
+
+    let mut config = config.unwrap();
+    assert_eq!(config.server, "ircs://irc.example.org");
+    assert_eq!(config.channel, "anime");
+    assert_eq!(config.nickname, "someRustacean");
+    assert_eq!(config.tls_ca, Some(PathBuf::from("/etc/ssl/ca.pem")));
+    assert!(!config.tls);
+
+    // Paths may contain spaces
+    config.set("tls_ca", "/tmp/my certs/ca.pem").unwrap();
+    assert_eq!(config.tls_ca, Some(PathBuf::from("/tmp/my certs/ca.pem")));
+    assert!(config.set("tls_ca", "").is_err());
+}
+
+#[test]
+fn environment_overrides_the_config_file() {
+    let mut config = Config::default();
+    config.apply_str("server = irc.example.org\nnickname = fromFile").unwrap();
+    let vars = vec![
+        ("ANIME_CLI_NICKNAME".to_string(), "fromEnv".to_string()),
+        ("ANIME_CLI_TLS".to_string(), "true".to_string()),
+        ("ANIME_CLI_VERSION".to_string(), "not a setting".to_string()),
+        ("NICKNAME".to_string(), "someoneElse".to_string()),
+    ];
+    config.apply_vars(vars).unwrap();
+
+    assert_eq!(config.server, "irc.example.org");
+    assert_eq!(config.nickname, "fromEnv");
+    assert!(config.tls);
+}
+
+#[test]
+fn rejects_invalid_settings() {
+    let invalid = |text: &str| match Config::default().apply_str(text) {
+        Err(Error::InvalidInput(message)) => message,
+        other => panic!("expected an invalid input error for {:?}, got {:?}", text, other),
+    };
+    assert!(invalid("server").contains("line 1"));
+    assert!(invalid("\nnick = someone").contains("unknown setting 'nick'"));
+    assert!(invalid("tls = maybe").contains("expected true or false"));
+    assert!(invalid("nickname = two words").contains("invalid nickname"));
+}
+
+#[test]
+fn accepts_spaces_in_passwords_only() {
+    let mut config = Config::default();
+    config.apply_str("account = someAccount\npassword = correct horse battery staple").unwrap();
+    let credentials = config.credentials().unwrap();
+    assert_eq!(credentials.account, "someAccount");
+    assert_eq!(credentials.password, "correct horse battery staple");
+
+    // NickServ would take the second word for the password
+    assert!(config.set("account", "some account").is_err());
+    assert!(config.set("password", "").is_err());
+    assert!(config.set("account", "").is_err());
+}
+
+#[test]
+fn randomizes_the_default_nickname() {
+    let nickname = random_nickname();
+    let suffix = nickname.trim_start_matches("randomRustacean");
+    assert_eq!(suffix.len(), 4);
+    assert!(suffix.chars().all(|c| c.is_ascii_digit()));
+    assert!(Config::default().nickname.starts_with("randomRustacean"));
+}
@@ -0,0 +1,291 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anime_cli::anime_output::part_path;
+use anime_cli::{connect_and_download, list_packs, Error, Event};
+
+const CONTENT: &[u8] = b"episode content";
+
+#[test]
+fn only_accepts_requested_offers_from_the_bot() {
+    let (filename, spoofed, unrequested) = ("offer.mkv", "spoofed.txt", "unrequested.mkv");
+
+    let server = common::start_irc_server(move |line| {
+        if line == "PRIVMSG Bot :xdcc send #1" {
+            let offer = |filename: &str| {
+                let port = common::start_dcc_sender(CONTENT.to_vec());
+                common::dcc_send("Bot", filename, port, CONTENT.len())
+            };
+            vec![
+                // Someone else in the channel
+                offer(spoofed).replacen(":Bot!", ":Mallory!", 1),
+                // Bots send their offers as PRIVMSG
+                offer(filename).replacen(" PRIVMSG ", " NOTICE ", 1),
+                offer(unrequested),
+                offer(filename),
+            ]
+        } else {
+            common::network(line)
+        }
+    });
+
+    let output_dir = common::output_dir("offers");
+    let mut request = common::request(server, "Bot", vec![1]);
+    request.filenames = vec![filename.to_string()];
+    request.output_dir = output_dir.clone();
+    let (events, listener) = common::recorder();
+    let result = connect_and_download(request, Arc::new(AtomicBool::new(false)), listener);
+    let content = std::fs::read(output_dir.join(filename));
+    let (spoofed_exists, unrequested_exists) = (output_dir.join(spoofed).exists(), output_dir.join(unrequested).exists());
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    result.unwrap();
+    assert_eq!(content.unwrap(), CONTENT);
+    assert!(!spoofed_exists);
+    assert!(!unrequested_exists);
+
+    let rejections = events
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|event| match event {
+            Event::OfferRejected { sender, reason, .. } => Some((sender.clone(), reason.clone())),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rejections,
+        vec![
+            ("Mallory".to_string(), "not sent by Bot".to_string()),
+            ("Bot".to_string(), "sent as NOTICE, not PRIVMSG".to_string()),
+            ("Bot".to_string(), "not a requested file".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn ignores_offers_before_any_request() {
+    let server = common::start_irc_server(move |line| {
+        if line.starts_with("USER ") {
+            // Sent before we even joined the channel
+            let port = common::start_dcc_sender(CONTENT.to_vec());
+            let mut replies = vec![common::dcc_send("Bot", "early.mkv", port, CONTENT.len())];
+            replies.extend(common::network(line));
+            replies
+        } else if line == "PRIVMSG Bot :xdcc send #1" {
+            let port = common::start_dcc_sender(CONTENT.to_vec());
+            vec![common::dcc_send("Bot", "early.mkv", port, CONTENT.len())]
+        } else {
+            common::network(line)
+        }
+    });
+
+    let (events, listener) = common::recorder();
+    let output_dir = common::output_dir("early");
+    let mut request = common::request(server, "Bot", vec![1]);
+    request.output_dir = output_dir.clone();
+    let result = connect_and_download(request, Arc::new(AtomicBool::new(false)), listener);
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    result.unwrap();
+    let events = events.lock().unwrap();
+    assert!(events.iter().any(|event| matches!(
+        event,
+        Event::OfferRejected { reason, .. } if reason == "no package was requested yet"
+    )));
+    assert_eq!(events.iter().filter(|event| matches!(event, Event::Completed { .. })).count(), 1);
+}
+
+#[test]
+fn keeps_offered_files_in_the_output_directory() {
+    let server = common::start_irc_server(move |line| {
+        if line == "PRIVMSG Bot :xdcc send #1" {
+            let port = common::start_dcc_sender(CONTENT.to_vec());
+            vec![common::dcc_send("Bot", "../[Group]_Some_Show_-_01_[720p].mkv", port, CONTENT.len())]
+        } else {
+            common::network(line)
+        }
+    });
+
+    let output_dir = common::output_dir("escape");
+    let mut request = common::request(server, "Bot", vec![1]);
+    request.output_dir = output_dir.join("downloads");
+    request.subfolder = Some("{series}".to_string());
+    let (_, listener) = common::recorder();
+    let result = connect_and_download(request, Arc::new(AtomicBool::new(false)), listener);
+    let saved = output_dir.join("downloads").join("Some Show").join("[Group]_Some_Show_-_01_[720p].mkv");
+    let (content, escaped) = (std::fs::read(saved), output_dir.join("[Group]_Some_Show_-_01_[720p].mkv").exists());
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    result.unwrap();
+    assert_eq!(content.unwrap(), CONTENT);
+    assert!(!escaped);
+}
+
+/// Serves `chunks` like a strict bot, sending each one once the previous one is
+/// acknowledged with `ack_size` bytes. Returns the port and the acknowledged positions.
+fn start_strict_sender(chunks: Vec<&'static [u8]>, ack_size: usize) -> (u16, Arc<Mutex<Vec<u64>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let acks = Arc::new(Mutex::new(Vec::new()));
+    let received = acks.clone();
+    thread::spawn(move || {
+        let (mut client, _) = listener.accept().unwrap();
+        let mut sent = 0;
+        for chunk in chunks {
+            client.write_all(chunk).unwrap();
+            sent += chunk.len() as u64;
+            // Acknowledgements may come in pieces
+            let mut ack = vec![0; ack_size];
+            while received.lock().unwrap().last() != Some(&sent) {
+                if client.read_exact(&mut ack).is_err() {
+                    return;
+                }
+                let position = ack.iter().fold(0, |position, &byte| position << 8 | u64::from(byte));
+                received.lock().unwrap().push(position);
+            }
+        }
+    });
+    (port, acks)
+}
+
+fn download_from(port: u16, size: u64, output_dir: &std::path::Path) -> Result<(), Error> {
+    let server = common::start_irc_server(move |line| {
+        if line == "PRIVMSG Bot :xdcc send #1" {
+            vec![common::dcc_send("Bot", "acked.mkv", port, size as usize)]
+        } else {
+            common::network(line)
+        }
+    });
+    let mut request = common::request(server, "Bot", vec![1]);
+    request.output_dir = output_dir.to_path_buf();
+    connect_and_download(request, Arc::new(AtomicBool::new(false)), common::recorder().1)
+}
+
+#[test]
+fn acknowledges_received_data() {
+    let (port, acks) = start_strict_sender(vec![&CONTENT[..5], &CONTENT[5..]], 4);
+    let output_dir = common::output_dir("acks");
+    let result = download_from(port, CONTENT.len() as u64, &output_dir);
+    let content = std::fs::read(output_dir.join("acked.mkv"));
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    result.unwrap();
+    assert_eq!(content.unwrap(), CONTENT);
+    assert_eq!(*acks.lock().unwrap(), vec![5, CONTENT.len() as u64]);
+}
+
+#[test]
+fn acknowledges_large_files_with_64_bits_and_fails_truncated_transfers() {
+    // Over 4 GiB, of which the bot only sends the beginning
+    let size = 5 * 1024 * 1024 * 1024;
+    let (port, acks) = start_strict_sender(vec![CONTENT], 8);
+    let output_dir = common::output_dir("large-acks");
+    let result = download_from(port, size, &output_dir);
+    let part = std::fs::read(part_path(&output_dir.join("acked.mkv")));
+    let complete = output_dir.join("acked.mkv").exists();
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    match result {
+        Err(Error::Dcc(message, _)) => assert!(message.contains("can be resumed"), "{}", message),
+        other => panic!("expected a DCC error, got {:?}", other),
+    }
+    assert_eq!(*acks.lock().unwrap(), vec![CONTENT.len() as u64]);
+    assert_eq!(part.unwrap(), CONTENT);
+    assert!(!complete);
+}
+
+#[test]
+fn connects_to_ipv6_and_hostname_addresses() {
+    for (name, ip, address) in [("ipv6", "::1", "::1"), ("hostname", "127.0.0.1", "localhost")].iter() {
+        let port = common::start_dcc_sender_on(ip, CONTENT.to_vec());
+        let offer = common::dcc_send_from("Bot", "addressed.mkv", address, port, CONTENT.len());
+        let server = common::start_irc_server(move |line| {
+            if line == "PRIVMSG Bot :xdcc send #1" {
+                vec![offer.clone()]
+            } else {
+                common::network(line)
+            }
+        });
+
+        let output_dir = common::output_dir(name);
+        let mut request = common::request(server, "Bot", vec![1]);
+        request.output_dir = output_dir.clone();
+        let result = connect_and_download(request, Arc::new(AtomicBool::new(false)), common::recorder().1);
+        let content = std::fs::read(output_dir.join("addressed.mkv"));
+        std::fs::remove_dir_all(&output_dir).ok();
+
+        result.unwrap_or_else(|e| panic!("{} offer: {}", name, e));
+        assert_eq!(content.unwrap(), CONTENT);
+    }
+}
+
+#[test]
+fn decodes_non_ascii_filenames() {
+    // Multibyte characters end up split across reads
+    let filename = "[Group]_Shōwa_Genroku_–_01_[720p].mkv";
+    let server = common::start_irc_server(move |line| {
+        if line == "PRIVMSG Bot :xdcc send #1" {
+            let port = common::start_dcc_sender(CONTENT.to_vec());
+            vec![common::dcc_send("Bot", filename, port, CONTENT.len())]
+        } else {
+            common::network(line)
+        }
+    });
+    let output_dir = common::output_dir("non-ascii");
+    let mut request = common::request(server, "Bot", vec![1]);
+    request.filenames = vec![filename.to_string()];
+    request.output_dir = output_dir.clone();
+    let result = connect_and_download(request, Arc::new(AtomicBool::new(false)), common::recorder().1);
+    let content = std::fs::read(output_dir.join(filename));
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    result.unwrap();
+    assert_eq!(content.unwrap(), CONTENT);
+}
+
+/// A bot offering its pack list over DCC, from `port`, as a file of `size` bytes.
+fn start_pack_list_bot(port: u16, size: usize) -> String {
+    common::start_irc_server(move |line| {
+        if line == "PRIVMSG Bot :xdcc list" {
+            vec![common::dcc_send("Bot", "packlist.txt", port, size)]
+        } else {
+            common::network(line)
+        }
+    })
+}
+
+#[test]
+fn refuses_oversized_pack_lists() {
+    let port = common::start_dcc_sender(CONTENT.to_vec());
+    let server = start_pack_list_bot(port, usize::MAX);
+    let result = list_packs(&common::request(server, "Bot", vec![]), Arc::new(AtomicBool::new(false)));
+
+    match result {
+        Err(Error::Dcc(message, _)) => assert!(message.contains("too large"), "{}", message),
+        other => panic!("expected Dcc, got {:?}", other),
+    }
+}
+
+#[test]
+fn stops_receiving_pack_lists_on_shutdown() {
+    // Sends nothing until we hang up
+    let sender = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = sender.local_addr().unwrap().port();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let flag = shutdown.clone();
+    thread::spawn(move || {
+        let (mut client, _) = sender.accept().unwrap();
+        flag.store(true, Ordering::SeqCst);
+        client.read_to_end(&mut Vec::new()).ok();
+    });
+    let server = start_pack_list_bot(port, 1024);
+    let result = list_packs(&common::request(server, "Bot", vec![]), shutdown);
+
+    assert!(matches!(result, Err(Error::Interrupted)), "{:?}", result);
+}
@@ -0,0 +1,65 @@
+use anime_cli::anime_episodes::{
+    find_episode, is_open_ended, parse_episodes, resolve_episodes, EpisodeParseError, EpisodeSpec,
+};
+
+#[test]
+fn parses_numbers_ranges_and_keywords() {
+    assert_eq!(
+        parse_episodes("1,3,7-9,12-,latest,ALL").unwrap(),
+        vec![
+            EpisodeSpec::Single(1),
+            EpisodeSpec::Single(3),
+            EpisodeSpec::Range(7, 9),
+            EpisodeSpec::From(12),
+            EpisodeSpec::Latest,
+            EpisodeSpec::All,
+        ]
+    );
+}
+
+#[test]
+fn rejects_invalid_specifications() {
+    assert_eq!(parse_episodes(""), Err(EpisodeParseError::Empty));
+    assert_eq!(parse_episodes("1,,2"), Err(EpisodeParseError::Empty));
+    assert_eq!(
+        parse_episodes("one"),
+        Err(EpisodeParseError::InvalidNumber("one".to_string()))
+    );
+    assert_eq!(
+        parse_episodes("-3"),
+        Err(EpisodeParseError::InvalidRange("-3".to_string()))
+    );
+    assert_eq!(
+        parse_episodes("9-7"),
+        Err(EpisodeParseError::InvalidRange("9-7".to_string()))
+    );
+}
+
+#[test]
+fn resolves_closed_forms_without_available_episodes() {
+    let specs = parse_episodes("3,1-4").unwrap();
+    assert!(!is_open_ended(&specs));
+    assert_eq!(resolve_episodes(&specs, &[]), vec![3, 1, 2, 4]);
+}
+
+#[test]
+fn resolves_open_forms_against_available_episodes() {
+    let available = [4, 1, 2, 7, 4];
+    let resolve = |spec: &str| {
+        let specs = parse_episodes(spec).unwrap();
+        assert!(is_open_ended(&specs));
+        resolve_episodes(&specs, &available)
+    };
+    assert_eq!(resolve("latest"), vec![7]);
+    assert_eq!(resolve("all"), vec![1, 2, 4, 7]);
+    assert_eq!(resolve("3-"), vec![4, 7]);
+    assert_eq!(resolve("8-"), Vec::<u16>::new());
+}
+
+#[test]
+fn finds_episodes_in_filenames() {
+    assert_eq!(find_episode("[Group] Show - 01 [720p].mkv"), Some((12, 1)));
+    assert_eq!(find_episode("Show E13v2 [1080p].mkv"), Some((5, 13)));
+    assert_eq!(find_episode("Show Ep.7 [480p].mkv"), Some((5, 7)));
+    assert_eq!(find_episode("Show [1080p].mkv"), None);
+}
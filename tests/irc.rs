@@ -0,0 +1,138 @@
+mod common;
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+
+use anime_cli::irc::{DccAddress, Message, Prefix};
+use anime_cli::list_packs;
+
+#[test]
+fn parses_messages() {
+    let message = Message::parse(":Bot|A!bot@rizon-1234.net PRIVMSG tester :hello there\r\n").unwrap();
+    assert_eq!(
+        message,
+        Message {
+            tags: vec![],
+            prefix: Some(Prefix {
+                nick: "Bot|A".to_string(),
+                user: Some("bot".to_string()),
+                host: Some("rizon-1234.net".to_string()),
+            }),
+            command: "PRIVMSG".to_string(),
+            params: vec!["tester".to_string()],
+            trailing: Some("hello there".to_string()),
+        }
+    );
+    assert!(message.is_from("bot|a"));
+    assert_eq!(message.arg(0), Some("tester"));
+    assert_eq!(message.arg(1), Some("hello there"));
+    assert_eq!(message.arg(2), None);
+
+    let message = Message::parse("PING irc.rizon.net").unwrap();
+    assert_eq!(message.prefix, None);
+    assert_eq!(message.command, "PING");
+    assert_eq!(message.params, vec!["irc.rizon.net"]);
+    assert_eq!(message.trailing, None);
+    assert_eq!(message.last_arg(), Some("irc.rizon.net"));
+
+    let message = Message::parse(":irc.test 433 * tester :Nickname is already in use").unwrap();
+    assert_eq!(message.nick(), Some("irc.test"));
+    assert_eq!(message.prefix.unwrap().user, None);
+    assert_eq!(message.command, "433");
+    assert_eq!(message.params, vec!["*", "tester"]);
+
+    // An empty trailing parameter is still there
+    let message = Message::parse(":irc.test CAP * LS :").unwrap();
+    assert_eq!(message.trailing, Some(String::new()));
+
+    assert_eq!(Message::parse(""), None);
+    assert_eq!(Message::parse(":irc.test"), None);
+}
+
+#[test]
+fn parses_tags() {
+    let message =
+        Message::parse("@time=2020-01-01T00:00:00Z;msg=a\\sb\\:c\\\\d;bot :Bot!bot@host NOTICE tester :hi").unwrap();
+    assert_eq!(
+        message.tags,
+        vec![
+            ("time".to_string(), "2020-01-01T00:00:00Z".to_string()),
+            ("msg".to_string(), "a b;c\\d".to_string()),
+            ("bot".to_string(), String::new()),
+        ]
+    );
+    assert_eq!(message.command, "NOTICE");
+    assert_eq!(message.trailing, Some("hi".to_string()));
+}
+
+#[test]
+fn extracts_ctcp_messages() {
+    let offer = Message::parse(":Bot!bot@host PRIVMSG tester :\x01DCC SEND file.mkv 2130706433 5000 42\x01").unwrap();
+    assert_eq!(offer.ctcp(), Some("DCC SEND file.mkv 2130706433 5000 42"));
+
+    let unterminated = Message::parse(":Bot!bot@host PRIVMSG tester :\x01VERSION").unwrap();
+    assert_eq!(unterminated.ctcp(), Some("VERSION"));
+
+    let text = Message::parse(":Bot!bot@host PRIVMSG tester :DCC SEND file.mkv 1 2 3").unwrap();
+    assert_eq!(text.ctcp(), None);
+}
+
+#[test]
+fn waits_for_its_own_join_and_answers_any_ping() {
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let received = lines.clone();
+    let server = common::start_irc_server(move |line| {
+        received.lock().unwrap().push(line.to_string());
+        if line.starts_with("JOIN ") {
+            vec![
+                ":someone!else@host JOIN :#nibl".to_string(),
+                ":tester!tester@host JOIN :#other".to_string(),
+                "PING :irc.test-42".to_string(),
+            ]
+        } else if line == "PONG :irc.test-42" {
+            common::network("JOIN #nibl")
+        } else if line.contains("xdcc list") {
+            let port = common::start_dcc_sender(b"#1 1x [1M] a.mkv\n".to_vec());
+            vec![common::dcc_send("Bot", "packlist.txt", port, 17)]
+        } else {
+            common::network(line)
+        }
+    });
+
+    let request = common::request(server, "Bot", vec![]);
+    let pack_list = list_packs(&request, Arc::new(AtomicBool::new(false))).unwrap();
+    assert_eq!(pack_list, "#1 1x [1M] a.mkv\n");
+
+    let lines = lines.lock().unwrap();
+    let pong = lines.iter().position(|line| line == "PONG :irc.test-42").unwrap();
+    let list = lines.iter().position(|line| line.contains("xdcc list")).unwrap();
+    assert!(pong < list);
+}
+
+#[test]
+fn parses_dcc_addresses() {
+    assert_eq!(
+        DccAddress::parse("2130706433"),
+        Some(DccAddress::Ip(IpAddr::V4(Ipv4Addr::LOCALHOST)))
+    );
+    assert_eq!(
+        DccAddress::parse("2001:db8::7"),
+        Some(DccAddress::Ip("2001:db8::7".parse().unwrap()))
+    );
+    assert_eq!(DccAddress::parse("[::1]"), Some(DccAddress::Ip(IpAddr::V6(Ipv6Addr::LOCALHOST))));
+    assert_eq!(
+        DccAddress::parse("192.168.1.2"),
+        Some(DccAddress::Ip(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2))))
+    );
+    assert_eq!(
+        DccAddress::parse("xdcc.example-bots.net"),
+        Some(DccAddress::Host("xdcc.example-bots.net".to_string()))
+    );
+
+    // Too large for an IPv4 address
+    assert_eq!(DccAddress::parse("4294967296"), None);
+    assert_eq!(DccAddress::parse("bad_host!"), None);
+    assert_eq!(DccAddress::parse("bots..net"), None);
+    assert_eq!(DccAddress::parse(""), None);
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+
+use anime_cli::config::{Config, NickStrategy};
+use anime_cli::{list_packs, Error, IRCRequest};
+
+const PACK_LIST: &str = "#1  3x [700M] [Group] Show - 01 [720p].mkv\n";
+
+/// A network where the `taken` nicknames are in use. Registers the client once it
+/// picks a free nickname, then sends it the pack list. Returns the server address
+/// and the nicknames tried by the client.
+fn start_server(taken: &'static [&'static str], refusal: &'static str) -> (String, Arc<Mutex<Vec<String>>>) {
+    let tried = Arc::new(Mutex::new(Vec::new()));
+    let nicknames = tried.clone();
+    let mut user_sent = false;
+    let address = common::start_irc_server(move |line| {
+        if line.starts_with("NICK ") || line.starts_with("USER ") {
+            let mut nicknames = nicknames.lock().unwrap();
+            let nickname = match line.strip_prefix("NICK ") {
+                Some(nickname) => {
+                    nicknames.push(nickname.to_string());
+                    nickname
+                }
+                None => {
+                    user_sent = true;
+                    nicknames.last().unwrap().as_str()
+                }
+            };
+            if taken.contains(&nickname) && line.starts_with("NICK ") {
+                vec![format!(":irc.test {} * {} :Nickname is already in use", refusal, nickname)]
+            } else if user_sent && !taken.contains(&nickname) {
+                common::network("USER tester 0 * tester")
+            } else {
+                vec![]
+            }
+        } else if let Some(channel) = line.strip_prefix("JOIN ") {
+            let nicknames = nicknames.lock().unwrap();
+            let nickname = nicknames.last().unwrap();
+            vec![format!(":{}!{}@test JOIN :{}", nickname, nickname, channel)]
+        } else if line.contains("xdcc list") {
+            // Addressed to the nickname we ended up with
+            let nicknames = nicknames.lock().unwrap();
+            let port = common::start_dcc_sender(PACK_LIST.as_bytes().to_vec());
+            let offer = common::dcc_send("Bot", "packlist.txt", port, PACK_LIST.len());
+            vec![offer.replacen("PRIVMSG tester ", &format!("PRIVMSG {} ", nicknames.last().unwrap()), 1)]
+        } else {
+            common::network(line)
+        }
+    });
+    (address, tried)
+}
+
+fn request(server: String, nick_strategy: NickStrategy) -> IRCRequest {
+    let mut request = common::request(server, "Bot", vec![]);
+    request.nick_strategy = nick_strategy;
+    request
+}
+
+#[test]
+fn retries_with_alternative_nicknames() {
+    let (server, tried) = start_server(&["tester", "tester_"], "433");
+    let pack_list = list_packs(&request(server, NickStrategy::Underscore), Arc::new(AtomicBool::new(false)));
+
+    assert_eq!(pack_list.unwrap(), PACK_LIST);
+    assert_eq!(*tried.lock().unwrap(), vec!["tester", "tester_", "tester__"]);
+}
+
+#[test]
+fn gives_up_once_the_alternatives_are_exhausted() {
+    let (server, tried) = start_server(&["tester", "tester1", "tester2", "tester3"], "436");
+    match list_packs(&request(server, NickStrategy::Number), Arc::new(AtomicBool::new(false))) {
+        Err(Error::Irc(message, _)) => assert!(message.contains("already in use and so are its alternatives")),
+        other => panic!("expected an IRC error, got {:?}", other.map(|_| ())),
+    }
+    assert_eq!(*tried.lock().unwrap(), vec!["tester", "tester1", "tester2", "tester3"]);
+}
+
+#[test]
+fn fails_right_away_on_invalid_nicknames() {
+    let (server, tried) = start_server(&["tester"], "432");
+    match list_packs(&request(server, NickStrategy::Underscore), Arc::new(AtomicBool::new(false))) {
+        Err(Error::InvalidInput(message)) => assert!(message.contains("'tester' as invalid")),
+        other => panic!("expected an invalid input error, got {:?}", other.map(|_| ())),
+    }
+    assert_eq!(*tried.lock().unwrap(), vec!["tester"]);
+}
+
+#[test]
+fn builds_alternative_nicknames() {
+    assert_eq!(NickStrategy::Underscore.alternative("nick", 2), Some("nick__".to_string()));
+    assert_eq!(NickStrategy::Number.alternative("nick", 2), Some("nick2".to_string()));
+    assert_eq!(NickStrategy::Random.alternative("nick", 1).unwrap().len(), 8);
+    assert_eq!(NickStrategy::Fail.alternative("nick", 1), None);
+
+    let mut config = Config::default();
+    config.apply_str("nick_strategy = random\nnick_attempts = 5").unwrap();
+    assert_eq!(config.nick_strategy, NickStrategy::Random);
+    assert_eq!(config.nick_attempts, 5);
+    assert!(config.set("nick_strategy", "other").is_err());
+}
@@ -0,0 +1,57 @@
+use std::path::{Path, PathBuf};
+
+use anime_cli::anime_output::{check_template, download_path, sanitize_filename, series_name};
+use anime_cli::Error;
+
+#[test]
+fn sanitizes_offered_filenames() {
+    assert_eq!(sanitize_filename("[Group] Show - 01 [720p].mkv"), "[Group] Show - 01 [720p].mkv");
+    assert_eq!(sanitize_filename("../../.bashrc"), "bashrc");
+    assert_eq!(sanitize_filename("/etc/passwd"), "passwd");
+    assert_eq!(sanitize_filename("..\\..\\evil.exe"), "evil.exe");
+    assert_eq!(sanitize_filename("show\x1b[2J.mkv"), "show[2J.mkv");
+    assert_eq!(sanitize_filename("what?.mkv"), "what_.mkv");
+    assert_eq!(sanitize_filename(".."), "download");
+    assert_eq!(sanitize_filename("folder/"), "download");
+}
+
+#[test]
+fn finds_series_names() {
+    assert_eq!(
+        series_name("[HorribleSubs] Steins Gate 0 - 01 [720p].mkv"),
+        Some("Steins Gate 0".to_string())
+    );
+    assert_eq!(series_name("[Group]_Some_Show_-_12v2_[1080p].mkv"), Some("Some Show".to_string()));
+    assert_eq!(series_name("Show (2019) E05.mp4"), Some("Show".to_string()));
+    assert_eq!(series_name("Dr. Stone - 03.mkv"), Some("Dr. Stone".to_string()));
+    assert_eq!(series_name("[Group] - 01.mkv"), None);
+}
+
+#[test]
+fn builds_download_paths_from_templates() {
+    let filename = "[HorribleSubs] Fate/Zero - 01 [720p].mkv";
+    let dir = Path::new("/anime");
+    assert_eq!(download_path(dir, None, filename), PathBuf::from("/anime/Zero - 01 [720p].mkv"));
+    assert_eq!(
+        download_path(dir, Some("{group}/{series} ({resolution})"), "[HorribleSubs] Steins Gate 0 - 01 [720p].mkv"),
+        PathBuf::from("/anime/HorribleSubs/Steins Gate 0 (720p)/[HorribleSubs] Steins Gate 0 - 01 [720p].mkv")
+    );
+    // Templates cannot leave the output directory
+    assert_eq!(
+        download_path(dir, Some("../{series}"), "../[G] Some Show - 01.mkv").parent(),
+        Some(Path::new("/anime/Some Show"))
+    );
+    assert_eq!(
+        download_path(Path::new(""), Some("{group}"), "Show - 01.mkv"),
+        PathBuf::from("Unknown/Show - 01.mkv")
+    );
+}
+
+#[test]
+fn rejects_unknown_placeholders() {
+    assert!(check_template("{series}/{resolution}").is_ok());
+    match check_template("{season}") {
+        Err(Error::InvalidInput(message)) => assert!(message.contains("{season}")),
+        other => panic!("expected invalid input, got {:?}", other),
+    }
+}
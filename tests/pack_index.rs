@@ -0,0 +1,46 @@
+use anime_cli::anime_filter::Preferences;
+use anime_cli::anime_index::{parse_pack_list, update_index};
+use anime_cli::{find_all_packages, LocalIndex};
+
+const PACK_LIST: &str = "** 2 packs **  1 of 5 slots open
+\x02#1\x02   12x [700M] [Group] Show - 01 [720p].mkv
+#12  0x [ 1.3G] \x0304[Other] Show - 12v2 [1080p].mkv\x03
+Total Offered: 2GB  Total Transferred: 8GB";
+
+#[test]
+fn parses_iroffer_pack_lists() {
+    let packages = parse_pack_list("Bot|A", PACK_LIST);
+    assert_eq!(packages.len(), 2);
+
+    assert_eq!(packages[0].number, 1);
+    assert_eq!(packages[0].bot, "Bot|A");
+    assert_eq!(packages[0].size, "700M");
+    assert_eq!(packages[0].filename, "[Group] Show - 01 [720p].mkv");
+    assert_eq!(packages[0].episode, Some(1));
+
+    assert_eq!(packages[1].number, 12);
+    assert_eq!(packages[1].size, "1.3G");
+    assert_eq!(packages[1].filename, "[Other] Show - 12v2 [1080p].mkv");
+    assert_eq!(packages[1].episode, Some(12));
+}
+
+#[test]
+fn replaces_the_packages_of_a_bot_in_the_index() {
+    let path = std::env::temp_dir().join(format!(
+        "anime-cli-test-{}-pack-index.json",
+        std::process::id()
+    ));
+    std::fs::remove_file(&path).ok();
+
+    update_index(&path, "Bot|A", parse_pack_list("Bot|A", PACK_LIST)).unwrap();
+    update_index(&path, "Bot|B", parse_pack_list("Bot|B", PACK_LIST)).unwrap();
+    let first_line = PACK_LIST.lines().nth(1).unwrap();
+    update_index(&path, "bot|a", parse_pack_list("bot|a", first_line)).unwrap();
+
+    let query = "show".to_string();
+    let found = find_all_packages(&LocalIndex::new(&path), &query, &None, &Preferences::default());
+    std::fs::remove_file(&path).ok();
+
+    let bots = found.unwrap().into_iter().map(|p| p.bot).collect::<Vec<_>>();
+    assert_eq!(bots, vec!["Bot|B", "Bot|B", "bot|a"]);
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, Shutdown, TcpStream};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anime_cli::config::Config;
+use anime_cli::{connect_and_download, list_packs};
+
+const CONTENT: &[u8] = b"episode content";
+const TOKEN: &str = "42";
+
+/// A bot behind NAT: it makes a passive offer, then connects to the address we
+/// answer with to send `content`. Records our answers.
+fn start_bot(request: &'static str, filename: &'static str, content: &'static [u8]) -> (String, Arc<Mutex<Vec<String>>>) {
+    let answers = Arc::new(Mutex::new(Vec::new()));
+    let recorded = answers.clone();
+    let server = common::start_irc_server(move |line| {
+        if line == format!("PRIVMSG Bot :{}", request) {
+            vec![format!(
+                ":Bot!bot@test PRIVMSG tester :\x01DCC SEND {} {} 0 {} {}\x01",
+                filename,
+                u32::from(Ipv4Addr::new(192, 168, 1, 2)),
+                content.len(),
+                TOKEN
+            )]
+        } else if let Some(answer) = line.strip_prefix("PRIVMSG Bot :\x01DCC SEND ") {
+            recorded.lock().unwrap().push(answer.trim_end_matches('\x01').to_string());
+            let fields = answer.split(' ').collect::<Vec<_>>();
+            let ip = Ipv4Addr::from(fields[1].parse::<u32>().unwrap());
+            let port = fields[2].parse::<u16>().unwrap();
+            thread::spawn(move || {
+                let mut client = TcpStream::connect((ip, port)).unwrap();
+                client.write_all(content).unwrap();
+                client.shutdown(Shutdown::Write).ok();
+                client.read_to_end(&mut Vec::new()).ok();
+            });
+            vec![]
+        } else {
+            common::network(line)
+        }
+    });
+    (server, answers)
+}
+
+#[test]
+fn answers_passive_offers_and_waits_for_the_bot() {
+    let (server, answers) = start_bot("xdcc send #1", "passive.mkv", CONTENT);
+    let output_dir = common::output_dir("passive");
+    let mut request = common::request(server, "Bot", vec![1]);
+    request.output_dir = output_dir.clone();
+    request.dcc_ports = Some(40100..=40199);
+    request.external_ip = Some("127.0.0.1".parse().unwrap());
+    let result = connect_and_download(request, Arc::new(AtomicBool::new(false)), common::recorder().1);
+    let content = std::fs::read(output_dir.join("passive.mkv"));
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    result.unwrap();
+    assert_eq!(content.unwrap(), CONTENT);
+    let answers = answers.lock().unwrap();
+    let fields = answers[0].split(' ').collect::<Vec<_>>();
+    assert_eq!(fields[0], "passive.mkv");
+    assert_eq!(fields[1], u32::from(Ipv4Addr::LOCALHOST).to_string());
+    assert!((40100..=40199).contains(&fields[2].parse::<u16>().unwrap()));
+    assert_eq!(&fields[3..], &[CONTENT.len().to_string().as_str(), TOKEN]);
+}
+
+#[test]
+fn receives_pack_lists_from_passive_offers() {
+    // Without an external address, the one of the IRC connection is given
+    let (server, answers) = start_bot("xdcc list", "packlist.txt", b"#1 1x [1M] a.mkv\n");
+    let request = common::request(server, "Bot", vec![]);
+    let pack_list = list_packs(&request, Arc::new(AtomicBool::new(false))).unwrap();
+
+    assert_eq!(pack_list, "#1 1x [1M] a.mkv\n");
+    let answers = answers.lock().unwrap();
+    assert!(answers[0].starts_with(&format!("packlist.txt {} ", u32::from(Ipv4Addr::LOCALHOST))));
+}
+
+#[test]
+fn reads_passive_dcc_settings() {
+    let mut config = Config::default();
+    config.apply_str("dcc_ports = 49152-49159\nexternal_ip = 203.0.113.7").unwrap();
+    assert_eq!(config.dcc_ports, Some(49152..=49159));
+    assert_eq!(config.external_ip, Some("203.0.113.7".parse().unwrap()));
+
+    config.set("dcc_ports", "5000").unwrap();
+    assert_eq!(config.dcc_ports, Some(5000..=5000));
+    assert!(config.set("dcc_ports", "5000-4000").is_err());
+    assert!(config.set("dcc_ports", "0").is_err());
+    assert!(config.set("external_ip", "somewhere").is_err());
+}
@@ -0,0 +1,148 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use anime_cli::anime_output::{part_path, PartialDownload};
+use anime_cli::{connect_and_download, Error, Event};
+
+const CONTENT: &[u8] = b"the whole episode content";
+// Tagged with the CRC32 of the content
+const FILENAME: &str = "show_[3931A106].mkv";
+
+/// A bot offering `FILENAME` for pack #1, sending `content` from the resume position
+/// it accepts.
+fn start_bot(content: &'static [u8], advertised_size: usize) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let position = Arc::new(AtomicUsize::new(0));
+    let resume_position = position.clone();
+    thread::spawn(move || {
+        let (mut client, _) = listener.accept().unwrap();
+        client.write_all(&content[position.load(Ordering::SeqCst)..]).unwrap();
+        client.shutdown(Shutdown::Write).ok();
+        client.read_to_end(&mut Vec::new()).ok();
+    });
+
+    common::start_irc_server(move |line| {
+        if line == "PRIVMSG Bot :xdcc send #1" {
+            vec![common::dcc_send("Bot", FILENAME, port, advertised_size)]
+        } else if let Some(resume) = line.strip_prefix("PRIVMSG Bot :\x01DCC RESUME ") {
+            let position = resume.trim_end_matches('\x01').rsplit(' ').next().unwrap();
+            resume_position.store(position.parse().unwrap(), Ordering::SeqCst);
+            vec![format!(
+                ":Bot!bot@test PRIVMSG tester :\x01DCC ACCEPT {} {} {}\x01",
+                FILENAME, port, position
+            )]
+        } else {
+            common::network(line)
+        }
+    })
+}
+
+fn download(server: String, output_dir: &Path) -> (Result<(), Error>, Vec<Event>) {
+    let mut request = common::request(server, "Bot", vec![1]);
+    request.output_dir = output_dir.to_path_buf();
+    let (events, listener) = common::recorder();
+    let result = connect_and_download(request, Arc::new(AtomicBool::new(false)), listener);
+    let events = events.lock().unwrap().clone();
+    (result, events)
+}
+
+#[test]
+fn resumes_from_the_part_file_and_renames_it() {
+    let output_dir = common::output_dir("resume");
+    let path = output_dir.join(FILENAME);
+    std::fs::write(part_path(&path), &CONTENT[..10]).unwrap();
+    let partial = PartialDownload {
+        size: CONTENT.len() as u64,
+        bot: "Bot".to_string(),
+        pack: Some(1),
+    };
+    partial.save(&path).unwrap();
+
+    let server = start_bot(CONTENT, CONTENT.len());
+    let (result, events) = download(server, &output_dir);
+    let content = std::fs::read(&path);
+    let leftovers = std::fs::read_dir(&output_dir).unwrap().count();
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    result.unwrap();
+    assert_eq!(content.unwrap(), CONTENT);
+    assert_eq!(leftovers, 1);
+    assert!(events.contains(&Event::ResumeRequested {
+        filename: FILENAME.to_string(),
+        position: 10,
+    }));
+    // The part received before counts in the checksum
+    assert!(events.contains(&Event::Verified {
+        filename: FILENAME.to_string(),
+        crc: 0x3931_A106,
+    }));
+}
+
+#[test]
+fn restarts_part_files_of_another_offer() {
+    let output_dir = common::output_dir("restart");
+    let path = output_dir.join(FILENAME);
+    // Without a sidecar, nothing tells the partial file is the offered one
+    std::fs::write(part_path(&path), b"something else").unwrap();
+
+    let server = start_bot(CONTENT, CONTENT.len());
+    let (result, events) = download(server, &output_dir);
+    let content = std::fs::read(&path);
+
+    result.unwrap();
+    assert_eq!(content.unwrap(), CONTENT);
+    assert!(!events.iter().any(|event| matches!(event, Event::ResumeRequested { .. })));
+
+    // Another bot may offer a different file of the same size under that name
+    std::fs::remove_file(&path).unwrap();
+    std::fs::write(part_path(&path), b"something else").unwrap();
+    let partial = PartialDownload {
+        size: CONTENT.len() as u64,
+        bot: "OtherBot".to_string(),
+        pack: Some(1),
+    };
+    partial.save(&path).unwrap();
+
+    let server = start_bot(CONTENT, CONTENT.len());
+    let (result, events) = download(server, &output_dir);
+    let content = std::fs::read(&path);
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    result.unwrap();
+    assert_eq!(content.unwrap(), CONTENT);
+    assert!(!events.iter().any(|event| matches!(event, Event::ResumeRequested { .. })));
+}
+
+#[test]
+fn keeps_the_part_file_of_an_interrupted_transfer() {
+    let output_dir = common::output_dir("truncated");
+    let path = output_dir.join(FILENAME);
+
+    let server = start_bot(&CONTENT[..10], CONTENT.len());
+    let (result, _) = download(server, &output_dir);
+    let part = std::fs::read(part_path(&path));
+    let (complete, partial) = (path.exists(), PartialDownload::load(&path));
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    match result {
+        Err(Error::Dcc(message, _)) => assert!(message.contains("10 of 25 bytes"), "{}", message),
+        other => panic!("expected a DCC error, got {:?}", other),
+    }
+    assert!(!complete);
+    assert_eq!(part.unwrap(), &CONTENT[..10]);
+    assert_eq!(
+        partial,
+        Some(PartialDownload {
+            size: CONTENT.len() as u64,
+            bot: "Bot".to_string(),
+            pack: Some(1),
+        })
+    );
+}
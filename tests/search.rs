@@ -0,0 +1,162 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anime_cli::anime_filter::{rank, Preferences, ReleaseTags};
+use anime_cli::{
+    find_all_packages, find_packages, find_packages_by_episode, DCCPackage, Error, LocalIndex,
+    SearchProvider,
+};
+
+fn package(number: i32, bot: &str, filename: &str, episode: Option<u16>) -> DCCPackage {
+    DCCPackage {
+        number,
+        bot: bot.to_string(),
+        filename: filename.to_string(),
+        size: "300M".to_string(),
+        episode,
+    }
+}
+
+fn season() -> Vec<DCCPackage> {
+    vec![
+        package(1, "Bot|A", "[Group] Show - 01 [720p].mkv", Some(1)),
+        package(2, "Bot|B", "[Other] Show - 01 [1080p].mkv", Some(1)),
+        package(3, "Bot|B", "[Group] Show - 02 [1080p].mkv", Some(2)),
+        package(4, "Bot|A", "[Group] Show - 02 [720p].mkv", Some(2)),
+    ]
+}
+
+/// Serves a fixed list of packages and records every search made.
+struct FakeProvider {
+    packages: Vec<DCCPackage>,
+    searches: Mutex<Vec<Option<u16>>>,
+    // Whether searching without an episode finds anything
+    global_search: bool,
+}
+
+impl FakeProvider {
+    fn new(packages: Vec<DCCPackage>, global_search: bool) -> FakeProvider {
+        FakeProvider {
+            packages,
+            searches: Mutex::new(Vec::new()),
+            global_search,
+        }
+    }
+}
+
+impl SearchProvider for FakeProvider {
+    fn search(&self, _query: &str, episode: &Option<u16>) -> Result<Vec<DCCPackage>, Error> {
+        self.searches.lock().unwrap().push(*episode);
+        if episode.is_none() && !self.global_search {
+            return Ok(Vec::new());
+        }
+        Ok(self
+            .packages
+            .iter()
+            .filter(|p| episode.is_none() || p.episode == *episode)
+            .map(|p| package(p.number, &p.bot, &p.filename, p.episode))
+            .collect())
+    }
+}
+
+#[test]
+fn parses_release_tags() {
+    let tags = ReleaseTags::parse("[SubsPlease] Show - 03 (1080p) [1234ABCD].mkv");
+    assert_eq!(tags.group.as_deref(), Some("SubsPlease"));
+    assert_eq!(tags.resolution.as_deref(), Some("1080p"));
+
+    let tags = ReleaseTags::parse("[Group] Show [2019][1920x1080].mkv");
+    assert_eq!(tags.resolution.as_deref(), Some("1080p"));
+
+    assert_eq!(ReleaseTags::parse("Show - 01.mkv"), ReleaseTags::default());
+}
+
+#[test]
+fn filters_by_resolution_group_and_exclusions() {
+    let preferences = Preferences {
+        resolution: Some("1080".to_string()),
+        group: Some("group".to_string()),
+        exclude: vec![],
+    };
+    let numbers = |pkgs: Vec<DCCPackage>| pkgs.iter().map(|p| p.number).collect::<Vec<_>>();
+    assert_eq!(numbers(preferences.filter(season())), vec![3]);
+
+    let preferences = Preferences {
+        exclude: vec!["OTHER".to_string(), "- 02".to_string()],
+        ..Preferences::default()
+    };
+    assert_eq!(numbers(preferences.filter(season())), vec![1]);
+}
+
+#[test]
+fn ranks_packages_of_the_same_release_first() {
+    let reference = package(1, "Bot|A", "[Group] Show - 01 [720p].mkv", Some(1));
+    let mut candidates = season().split_off(2);
+    rank(&mut candidates, &reference);
+    assert_eq!(candidates[0].number, 4);
+}
+
+#[test]
+fn finds_all_episodes_with_a_single_search() {
+    let provider = FakeProvider::new(season(), true);
+    let query = "show".to_string();
+    let packages = find_packages(&provider, &query, &[1, 2], &Preferences::default(), 1).unwrap();
+
+    // Episode 2 comes from the same release as episode 1
+    assert_eq!(packages.iter().map(|p| p.number).collect::<Vec<_>>(), vec![1, 4]);
+    assert_eq!(*provider.searches.lock().unwrap(), vec![None]);
+}
+
+#[test]
+fn searches_missing_episodes_one_by_one() {
+    let provider = FakeProvider::new(season(), false);
+    let query = "show".to_string();
+    let by_episode =
+        find_packages_by_episode(&provider, &query, &[2, 1], &Preferences::default(), 2).unwrap();
+
+    assert_eq!(by_episode.iter().map(|(ep, _)| *ep).collect::<Vec<_>>(), vec![2, 1]);
+    assert_eq!(by_episode[0].1.len(), 2);
+    let mut searches = provider.searches.lock().unwrap().clone();
+    searches.sort();
+    assert_eq!(searches, vec![None, Some(1), Some(2)]);
+}
+
+#[test]
+fn reports_missing_episodes_as_not_found() {
+    let provider = FakeProvider::new(season(), true);
+    let query = "show".to_string();
+    match find_packages(&provider, &query, &[1, 3], &Preferences::default(), 1) {
+        Err(e @ Error::NotFound(_)) => {
+            assert!(e.to_string().starts_with("Episode 3: "));
+            assert_eq!(e.exit_code(), 3);
+        }
+        other => panic!("unexpected result: {:?}", other.map(|p| p.len())),
+    }
+}
+
+#[test]
+fn searches_a_local_index() {
+    let path: PathBuf = std::env::temp_dir().join(format!(
+        "anime-cli-test-{}-local-index.json",
+        std::process::id()
+    ));
+    std::fs::write(&path, serde_json::to_string(&season()).unwrap()).unwrap();
+
+    let index = LocalIndex::new(&path);
+    let query = "SHOW group".to_string();
+    let found = find_all_packages(&index, &query, &Some(2), &Preferences::default());
+    std::fs::remove_file(&path).ok();
+
+    let numbers = found.unwrap().iter().map(|p| p.number).collect::<Vec<_>>();
+    assert_eq!(numbers, vec![3, 4]);
+}
+
+#[test]
+fn reports_a_missing_local_index_as_a_filesystem_error() {
+    let index = LocalIndex::new("/nonexistent/anime-cli/index.json");
+    let query = "show".to_string();
+    match find_all_packages(&index, &query, &None, &Preferences::default()) {
+        Err(e @ Error::Filesystem(_, _)) => assert_eq!(e.exit_code(), 9),
+        other => panic!("unexpected result: {:?}", other.map(|p| p.len())),
+    }
+}
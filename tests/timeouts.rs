@@ -0,0 +1,171 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anime_cli::anime_output::part_path;
+use anime_cli::config::{Config, Timeouts};
+use anime_cli::{connect_and_download, list_packs, Error};
+
+const CONTENT: &[u8] = b"episode content";
+const QUEUED: &str = ":Bot!bot@test NOTICE tester :** All Slots Full, Added you to the main queue for pack 1 in position 1.";
+
+#[test]
+fn reads_timeout_settings() {
+    let mut config = Config::default();
+    config.apply_str("offer_timeout = 300\nping_interval = 90").unwrap();
+    assert_eq!(config.timeouts.offer, Duration::from_secs(300));
+    assert_eq!(config.timeouts.ping_interval, Duration::from_secs(90));
+    assert_eq!(config.timeouts.connect, Timeouts::default().connect);
+
+    config.set("stall_timeout", "5").unwrap();
+    assert_eq!(config.timeouts.stall, Duration::from_secs(5));
+    assert!(config.set("join_timeout", "0").is_err());
+    assert!(config.set("registration_timeout", "soon").is_err());
+}
+
+#[test]
+fn gives_up_on_bots_which_do_not_answer() {
+    let server = common::start_irc_server(common::network);
+    let mut request = common::request(server, "Bot", vec![1]);
+    request.timeouts = Timeouts {
+        offer: Duration::from_secs(1),
+        ..Timeouts::default()
+    };
+    let result = connect_and_download(request, Arc::new(AtomicBool::new(false)), common::recorder().1);
+
+    match result {
+        Err(Error::BotUnavailable(message)) => assert!(message.contains("Bot did not answer"), "{}", message),
+        other => panic!("expected BotUnavailable, got {:?}", other),
+    }
+}
+
+#[test]
+fn waits_for_slow_pack_lists() {
+    // The list streams for longer than the offer timeout
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let server = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let writer = stream.try_clone().unwrap();
+        common::serve(stream, move |line| {
+            if line == "PRIVMSG Bot :xdcc list" {
+                let mut writer = writer.try_clone().unwrap();
+                thread::spawn(move || {
+                    for pack in 1..=5 {
+                        thread::sleep(Duration::from_millis(400));
+                        let line = format!(":Bot!bot@test NOTICE tester :#{} 1x [1M] {}.mkv\r\n", pack, pack);
+                        writer.write_all(line.as_bytes()).unwrap();
+                    }
+                });
+                vec![]
+            } else {
+                common::network(line)
+            }
+        });
+    });
+    let mut request = common::request(server, "Bot", vec![]);
+    request.timeouts = Timeouts {
+        offer: Duration::from_secs(1),
+        ..Timeouts::default()
+    };
+    let pack_list = list_packs(&request, Arc::new(AtomicBool::new(false))).unwrap();
+
+    assert_eq!(pack_list.lines().count(), 5);
+}
+
+#[test]
+fn pings_the_server_while_queued() {
+    // The offer only comes once we PING, long after the offer timeout
+    let server = common::start_irc_server(|line| {
+        if line == "PRIVMSG Bot :xdcc send #1" {
+            vec![QUEUED.to_string()]
+        } else if line.starts_with("PING ") {
+            let port = common::start_dcc_sender(CONTENT.to_vec());
+            vec![
+                ":irc.test PONG irc.test :anime-cli".to_string(),
+                common::dcc_send("Bot", "queued.mkv", port, CONTENT.len()),
+            ]
+        } else {
+            common::network(line)
+        }
+    });
+    let output_dir = common::output_dir("keepalive");
+    let mut request = common::request(server, "Bot", vec![1]);
+    request.output_dir = output_dir.clone();
+    request.timeouts = Timeouts {
+        offer: Duration::from_secs(1),
+        ping_interval: Duration::from_secs(2),
+        ..Timeouts::default()
+    };
+    let start = Instant::now();
+    let result = connect_and_download(request, Arc::new(AtomicBool::new(false)), common::recorder().1);
+    let content = std::fs::read(output_dir.join("queued.mkv"));
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    result.unwrap();
+    assert_eq!(content.unwrap(), CONTENT);
+    assert!(start.elapsed() >= Duration::from_secs(2));
+}
+
+#[test]
+fn notices_when_the_server_stops_answering() {
+    // Not even our PINGs
+    let server = common::start_irc_server(|line| {
+        if line == "PRIVMSG Bot :xdcc send #1" {
+            vec![QUEUED.to_string()]
+        } else {
+            common::network(line)
+        }
+    });
+    let mut request = common::request(server, "Bot", vec![1]);
+    request.timeouts = Timeouts {
+        ping_interval: Duration::from_secs(1),
+        ..Timeouts::default()
+    };
+    let result = connect_and_download(request, Arc::new(AtomicBool::new(false)), common::recorder().1);
+
+    match result {
+        Err(Error::Network(message, _)) => assert!(message.contains("stopped answering"), "{}", message),
+        other => panic!("expected Network, got {:?}", other),
+    }
+}
+
+#[test]
+fn fails_stalled_transfers() {
+    // Sends half of the file, then nothing until we hang up
+    let sender = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = sender.local_addr().unwrap().port();
+    thread::spawn(move || {
+        let (mut client, _) = sender.accept().unwrap();
+        client.write_all(&CONTENT[..7]).unwrap();
+        client.read_to_end(&mut Vec::new()).ok();
+    });
+    let server = common::start_irc_server(move |line| {
+        if line == "PRIVMSG Bot :xdcc send #1" {
+            vec![common::dcc_send("Bot", "stalled.mkv", port, CONTENT.len())]
+        } else {
+            common::network(line)
+        }
+    });
+    let output_dir = common::output_dir("stalled");
+    let mut request = common::request(server, "Bot", vec![1]);
+    request.output_dir = output_dir.clone();
+    request.timeouts = Timeouts {
+        stall: Duration::from_secs(1),
+        ..Timeouts::default()
+    };
+    let result = connect_and_download(request, Arc::new(AtomicBool::new(false)), common::recorder().1);
+    let part = std::fs::read(part_path(&output_dir.join("stalled.mkv")));
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    match result {
+        Err(Error::Dcc(message, _)) => assert!(message.contains("stalled"), "{}", message),
+        other => panic!("expected Dcc, got {:?}", other),
+    }
+    assert_eq!(part.unwrap(), &CONTENT[..7]);
+}
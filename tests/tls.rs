@@ -0,0 +1,50 @@
+mod common;
+
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use anime_cli::connection::ServerAddress;
+use anime_cli::{list_packs, Error, IRCRequest};
+
+fn request(port: u16, tls_ca: Option<&str>) -> IRCRequest {
+    let mut request = common::request(format!("ircs://localhost:{}", port), "Bot", vec![]);
+    request.tls_ca = tls_ca.map(|name| common::fixture(name).into());
+    request
+}
+
+#[test]
+fn parses_server_addresses() {
+    let parse = |server: &str, tls: bool| ServerAddress::parse(server, tls).unwrap();
+    let address = |host: &str, port: u16, tls: bool| ServerAddress {
+        host: host.to_string(),
+        port,
+        tls,
+    };
+
+    assert_eq!(parse("irc.rizon.net:6667", false), address("irc.rizon.net", 6667, false));
+    assert_eq!(parse("irc.rizon.net", false), address("irc.rizon.net", 6667, false));
+    assert_eq!(parse("irc.rizon.net", true), address("irc.rizon.net", 6697, true));
+    assert_eq!(parse("ircs://irc.rizon.net", false), address("irc.rizon.net", 6697, true));
+    assert_eq!(parse("ircs://irc.rizon.net:9999/", false), address("irc.rizon.net", 9999, true));
+    assert_eq!(parse("irc://irc.rizon.net", true), address("irc.rizon.net", 6667, false));
+    assert_eq!(parse("ircs://[::1]:7000", false), address("::1", 7000, true));
+
+    assert!(ServerAddress::parse("irc.rizon.net:port", false).is_err());
+    assert!(ServerAddress::parse("ircs://", false).is_err());
+}
+
+#[test]
+fn talks_to_a_server_trusted_through_a_custom_ca() {
+    let port = common::start_tls_server();
+    let pack_list = list_packs(&request(port, Some("ca.pem")), Arc::new(AtomicBool::new(false))).unwrap();
+    assert_eq!(pack_list, common::PACK_LIST);
+}
+
+#[test]
+fn refuses_an_untrusted_certificate() {
+    let port = common::start_tls_server();
+    match list_packs(&request(port, None), Arc::new(AtomicBool::new(false))) {
+        Err(Error::Network(message, Some(_))) => assert!(message.contains("TLS handshake")),
+        other => panic!("expected a TLS error, got {:?}", other.map(|_| ())),
+    }
+}
@@ -0,0 +1,56 @@
+//! Kept apart from the other TLS tests: the test authority is made a system one here.
+mod common;
+
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Once};
+
+use anime_cli::config::Config;
+use anime_cli::{list_packs, Error, IRCRequest};
+
+static TRUST_TEST_CA: Once = Once::new();
+
+/// A request to the TLS server trusting `tls_ca`, pinned or not. The test authority
+/// is trusted by the system, like a public authority would be.
+fn request(port: u16, tls_ca: Option<&str>, tls_pin: bool) -> IRCRequest {
+    TRUST_TEST_CA.call_once(|| std::env::set_var("SSL_CERT_FILE", common::fixture("ca.pem")));
+    let mut request = common::request(format!("ircs://localhost:{}", port), "Bot", vec![]);
+    request.tls_ca = tls_ca.map(|name| common::fixture(name).into());
+    request.tls_pin = tls_pin;
+    request
+}
+
+#[test]
+fn trusts_system_authorities_unless_pinned() {
+    // other.pem is a valid authority, but not the one of the server
+    let port = common::start_tls_server();
+    let pack_list = list_packs(&request(port, Some("other.pem"), false), Arc::new(AtomicBool::new(false))).unwrap();
+    assert_eq!(pack_list, common::PACK_LIST);
+
+    let port = common::start_tls_server();
+    match list_packs(&request(port, Some("other.pem"), true), Arc::new(AtomicBool::new(false))) {
+        Err(Error::Network(message, Some(_))) => assert!(message.contains("TLS handshake")),
+        other => panic!("expected a TLS error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn talks_to_a_pinned_server() {
+    let port = common::start_tls_server();
+    let pack_list = list_packs(&request(port, Some("ca.pem"), true), Arc::new(AtomicBool::new(false))).unwrap();
+    assert_eq!(pack_list, common::PACK_LIST);
+
+    // Pinning nothing is a mistake
+    let port = common::start_tls_server();
+    assert!(matches!(
+        list_packs(&request(port, None, true), Arc::new(AtomicBool::new(false))),
+        Err(Error::InvalidInput(_))
+    ));
+}
+
+#[test]
+fn reads_the_pin_setting() {
+    let mut config = Config::default();
+    config.apply_str("tls_ca = /etc/ssl/server.pem\ntls_pin = yes").unwrap();
+    assert!(config.tls_pin);
+    assert!(config.set("tls_pin", "maybe").is_err());
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use anime_cli::anime_verify::{expected_crc, file_crc, verify_file, MismatchAction, Verification};
+use anime_cli::{connect_and_download, Error, Event};
+
+// CRC32 of "123456789"
+const CONTENT: &[u8] = b"123456789";
+const CRC: u32 = 0xCBF4_3926;
+
+#[test]
+fn reads_the_crc_of_filenames() {
+    assert_eq!(expected_crc("[Group] Show - 01 [720p][CBF43926].mkv"), Some(CRC));
+    assert_eq!(expected_crc("[Group] Show - 01 (cbf43926).mkv"), Some(CRC));
+    // Only the last tag is the checksum
+    assert_eq!(expected_crc("[DEADBEEF] Show - 01 [CBF43926].mkv"), Some(CRC));
+    assert_eq!(expected_crc("[Group] Show - 01 [1080p].mkv"), None);
+    assert_eq!(expected_crc("[Group] Show [2019].mkv"), None);
+}
+
+#[test]
+fn verifies_existing_files() {
+    let dir = common::output_dir("verify");
+    let valid = dir.join("Show - 01 [CBF43926].mkv");
+    let corrupted = dir.join("Show - 02 [CBF43926].mkv");
+    let unknown = dir.join("Show - 03.mkv");
+    std::fs::write(&valid, CONTENT).unwrap();
+    std::fs::write(&corrupted, b"12345678").unwrap();
+    std::fs::write(&unknown, CONTENT).unwrap();
+
+    let results = (
+        file_crc(&valid),
+        verify_file(&valid),
+        verify_file(&corrupted),
+        verify_file(&unknown),
+        verify_file(&dir.join("missing [CBF43926].mkv")),
+    );
+    std::fs::remove_dir_all(&dir).ok();
+
+    assert_eq!(results.0.unwrap(), CRC);
+    assert_eq!(results.1.unwrap(), Verification::Valid { crc: CRC });
+    assert_eq!(
+        results.2.unwrap(),
+        Verification::Mismatch {
+            expected: CRC,
+            actual: 0x9AE0_DAAF,
+        }
+    );
+    assert_eq!(results.3.unwrap(), Verification::Unknown);
+    assert!(matches!(results.4, Err(Error::Filesystem(_, _))));
+}
+
+/// Downloads `filename` served with `CONTENT` into a directory of its own.
+fn download(name: &str, filename: &'static str, on_mismatch: MismatchAction) -> (Result<(), Error>, Vec<Event>, Vec<String>) {
+    let server = common::start_irc_server(move |line| {
+        if line == "PRIVMSG Bot :xdcc send #1" {
+            let port = common::start_dcc_sender(CONTENT.to_vec());
+            vec![common::dcc_send("Bot", filename, port, CONTENT.len())]
+        } else {
+            common::network(line)
+        }
+    });
+    let output_dir = common::output_dir(name);
+    let mut request = common::request(server, "Bot", vec![1]);
+    request.output_dir = output_dir.clone();
+    request.on_mismatch = on_mismatch;
+    let (events, listener) = common::recorder();
+    let result = connect_and_download(request, Arc::new(AtomicBool::new(false)), listener);
+    let mut files = std::fs::read_dir(&output_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    files.sort();
+    std::fs::remove_dir_all(&output_dir).ok();
+    let events = events.lock().unwrap().clone();
+    (result, events, files)
+}
+
+#[test]
+fn verifies_downloads_while_receiving_them() {
+    let (result, events, files) = download("valid", "Show_-_01_[CBF43926].mkv", MismatchAction::Keep);
+    result.unwrap();
+    assert!(events.contains(&Event::Verified {
+        filename: "Show_-_01_[CBF43926].mkv".to_string(),
+        crc: CRC,
+    }));
+    assert_eq!(files, vec!["Show_-_01_[CBF43926].mkv"]);
+}
+
+#[test]
+fn fails_downloads_which_do_not_match_their_crc() {
+    let (result, events, files) = download("mismatch", "Show_-_01_[DEADBEEF].mkv", MismatchAction::Rename);
+    match result {
+        Err(Error::Corrupted(message)) => assert!(message.contains("CBF43926 instead of DEADBEEF"), "{}", message),
+        other => panic!("expected a corrupted file, got {:?}", other),
+    }
+    assert!(events.iter().any(|event| matches!(event, Event::Failed { .. })));
+    assert!(!events.iter().any(|event| matches!(event, Event::Completed { .. })));
+    assert_eq!(files, vec!["Show_-_01_[DEADBEEF].mkv.corrupt"]);
+
+    let (result, _, files) = download("delete", "Show_-_01_[DEADBEEF].mkv", MismatchAction::Delete);
+    assert!(matches!(result, Err(Error::Corrupted(_))));
+    assert!(files.is_empty());
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anime_cli::config::Timeouts;
+use anime_cli::xdcc::{strip_formatting, BotReply};
+use anime_cli::{connect_and_download, list_packs, Error, Event};
+
+const CONTENT: &[u8] = b"episode content";
+
+#[test]
+fn parses_iroffer_replies() {
+    let queued = "** All Slots Full, Added you to the main queue for pack 1 (\"Show - 01.mkv\") in position 3. \
+                  To Remove yourself at a later time type \"/MSG Bot XDCC REMOVE 1\".";
+    assert_eq!(BotReply::parse(queued), Some(BotReply::Queued { position: Some(3) }));
+    assert_eq!(
+        BotReply::parse("** You already have that item queued in position 2"),
+        Some(BotReply::Queued { position: Some(2) })
+    );
+    assert_eq!(
+        BotReply::parse("You have been queued in position: 7"),
+        Some(BotReply::Queued { position: Some(7) })
+    );
+    assert_eq!(BotReply::parse("** Invalid Pack Number, Try Again"), Some(BotReply::InvalidPack));
+    assert_eq!(BotReply::parse("** You already requested that pack"), Some(BotReply::AlreadyRequested));
+    assert_eq!(
+        BotReply::parse("** All Slots Full, Main queue of size 10 is Full, Try Again Later"),
+        Some(BotReply::QueueFull)
+    );
+    assert_eq!(
+        BotReply::parse("** XDCC SEND denied, you must be on a known channel to request a pack"),
+        Some(BotReply::Denied)
+    );
+    assert_eq!(BotReply::parse("** Sending you pack #1 (\"Show - 01.mkv\"), which is 350MB"), None);
+}
+
+#[test]
+fn ignores_formatting_codes() {
+    let text = "\x02**\x02 \x0304,01Invalid Pack Number\x03, \x1fTry Again\x0f";
+    assert_eq!(strip_formatting(text), "** Invalid Pack Number, Try Again");
+    assert_eq!(BotReply::parse(text), Some(BotReply::InvalidPack));
+}
+
+#[test]
+fn fails_fast_on_invalid_packs() {
+    let server = common::start_irc_server(|line| {
+        if line == "PRIVMSG Bot :xdcc send #99" {
+            vec![":Bot!bot@test NOTICE tester :** Invalid Pack Number, Try Again".to_string()]
+        } else {
+            common::network(line)
+        }
+    });
+    let (events, listener) = common::recorder();
+    let result = connect_and_download(
+        common::request(server, "Bot", vec![99]),
+        Arc::new(AtomicBool::new(false)),
+        listener,
+    );
+
+    match result {
+        Err(Error::NotFound(message)) => assert!(message.contains("Invalid Pack Number"), "{}", message),
+        other => panic!("expected NotFound, got {:?}", other),
+    }
+    assert!(events
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|event| matches!(event, Event::Refused { bot, .. } if bot == "Bot")));
+}
+
+#[test]
+fn waits_in_the_queue_of_the_bot() {
+    let server = common::start_irc_server(|line| {
+        if line == "PRIVMSG Bot :xdcc send #1" {
+            let port = common::start_dcc_sender(CONTENT.to_vec());
+            vec![
+                ":Bot!bot@test NOTICE tester :** All Slots Full, Added you to the main queue for pack 1 \
+                 (\"queued.mkv\") in position 3."
+                    .to_string(),
+                common::dcc_send("Bot", "queued.mkv", port, CONTENT.len()),
+            ]
+        } else {
+            common::network(line)
+        }
+    });
+    let output_dir = common::output_dir("queued");
+    let mut request = common::request(server, "Bot", vec![1]);
+    request.output_dir = output_dir.clone();
+    let (events, listener) = common::recorder();
+    let result = connect_and_download(request, Arc::new(AtomicBool::new(false)), listener);
+    let content = std::fs::read(output_dir.join("queued.mkv"));
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    result.unwrap();
+    assert_eq!(content.unwrap(), CONTENT);
+    assert!(events
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|event| matches!(event, Event::Queued { position: Some(3), .. })));
+}
+
+#[test]
+fn ignores_announcements_in_the_channel() {
+    let server = common::start_irc_server(|line| {
+        if line == "PRIVMSG Bot :xdcc send #1" {
+            let port = common::start_dcc_sender(CONTENT.to_vec());
+            vec![
+                ":Bot!bot@test PRIVMSG #nibl :** All Slots Full, Main queue of size 10 is Full, Try Again Later"
+                    .to_string(),
+                common::dcc_send("Bot", "announced.mkv", port, CONTENT.len()),
+            ]
+        } else {
+            common::network(line)
+        }
+    });
+    let output_dir = common::output_dir("announced");
+    let mut request = common::request(server, "Bot", vec![1]);
+    request.output_dir = output_dir.clone();
+    let result = connect_and_download(request, Arc::new(AtomicBool::new(false)), common::recorder().1);
+    let content = std::fs::read(output_dir.join("announced.mkv"));
+    std::fs::remove_dir_all(&output_dir).ok();
+
+    result.unwrap();
+    assert_eq!(content.unwrap(), CONTENT);
+}
+
+#[test]
+fn leaves_channel_lines_out_of_pack_lists() {
+    let server = common::start_irc_server(|line| {
+        if line == "PRIVMSG Bot :xdcc list" {
+            vec![
+                ":Bot!bot@test PRIVMSG #nibl :#9 5x [1G] announced.mkv".to_string(),
+                ":Bot!bot@test NOTICE tester :#1 1x [1M] listed.mkv".to_string(),
+            ]
+        } else {
+            common::network(line)
+        }
+    });
+    let mut request = common::request(server, "Bot", vec![]);
+    request.timeouts = Timeouts {
+        offer: Duration::from_secs(1),
+        ..Timeouts::default()
+    };
+    let pack_list = list_packs(&request, Arc::new(AtomicBool::new(false))).unwrap();
+
+    assert!(pack_list.contains("listed.mkv"), "{}", pack_list);
+    assert!(!pack_list.contains("announced.mkv"), "{}", pack_list);
+}